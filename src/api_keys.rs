@@ -0,0 +1,232 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::authorization::Role;
+use crate::entities::{APIError, Claims, ErrorCode};
+use crate::token_store::{random_token, token_hash, write_json_file};
+
+// Every API key starts with this, so it can be told apart from a JWT and found by secret scanners
+pub const API_KEY_PREFIX: &str = "krk_";
+const API_KEY_ID_LEN: usize = 12;
+const API_KEY_SECRET_LEN: usize = 40;
+// last_used_at is written to disk in the background this often
+const LAST_USED_FLUSH_SECS: u64 = 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "role")]
+    pub role: Role,
+    #[serde(rename = "created_by")]
+    pub created_by: String,
+    #[serde(rename = "created_at")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "expires_at")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(rename = "last_used_at")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(rename = "revoked_at")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StoredApiKey {
+    #[serde(flatten)]
+    key: ApiKey,
+    #[serde(rename = "key_hash")]
+    key_hash: String,
+}
+
+/// Long lived keys for machine clients, stored hashed in a local JSON file.
+pub struct ApiKeyStore {
+    path: PathBuf,
+    keys: Mutex<Vec<StoredApiKey>>,
+    // held while writing, so an older copy of the keys never overwrites a newer one
+    write_lock: Mutex<()>,
+    // a last_used_at changed since the last write
+    unsaved_use: AtomicBool,
+}
+
+impl ApiKeyStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<ApiKeyStore, APIError> {
+        let path = path.into();
+        let keys: Vec<StoredApiKey> = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| {
+                APIError::new(&format!("Invalid API key store {}: {}", path.display(), e))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(APIError::new(&format!(
+                    "Failed to read API key store {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        info!("API key store {}: {} keys", path.display(), keys.len());
+        Ok(ApiKeyStore {
+            path,
+            keys: Mutex::new(keys),
+            write_lock: Mutex::new(()),
+            unsaved_use: AtomicBool::new(false),
+        })
+    }
+
+    /// Open the store at `API_KEY_STORE_FILE`, default `api_keys.json`.
+    pub fn from_env() -> Result<ApiKeyStore, APIError> {
+        ApiKeyStore::open(
+            std::env::var("API_KEY_STORE_FILE").unwrap_or("api_keys.json".to_string()),
+        )
+    }
+
+    /// Create a key and return it with its secret, which is not stored and can't be shown again.
+    pub fn create(
+        &self,
+        name: &str,
+        role: Role,
+        created_by: &str,
+        expires_in: Option<Duration>,
+    ) -> Result<(ApiKey, String), APIError> {
+        let id = random_token(API_KEY_ID_LEN);
+        let secret = format!(
+            "{}{}_{}",
+            API_KEY_PREFIX,
+            id,
+            random_token(API_KEY_SECRET_LEN)
+        );
+        let now = Utc::now();
+        let key = ApiKey {
+            id,
+            name: name.to_string(),
+            role,
+            created_by: created_by.to_string(),
+            created_at: now,
+            expires_at: expires_in.map(|d| now + d),
+            last_used_at: None,
+            revoked_at: None,
+        };
+        self.keys.lock().unwrap().push(StoredApiKey {
+            key: key.clone(),
+            key_hash: token_hash(&secret),
+        });
+        self.save()?;
+        info!("{} created API key {} ({:?})", created_by, key.id, role);
+        Ok((key, secret))
+    }
+
+    pub fn list(&self) -> Vec<ApiKey> {
+        self.keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|k| k.key.clone())
+            .collect()
+    }
+
+    pub fn revoke(&self, id: &str) -> Result<ApiKey, APIError> {
+        let key = {
+            let mut keys = self.keys.lock().unwrap();
+            let stored = keys.iter_mut().find(|k| k.key.id == id).ok_or_else(|| {
+                APIError::with_code(ErrorCode::NotFound, &format!("No API key {}", id))
+            })?;
+            stored.key.revoked_at.get_or_insert(Utc::now());
+            stored.key.clone()
+        };
+        self.save()?;
+        info!("Revoked API key {}", id);
+        Ok(key)
+    }
+
+    /// Claims for a request made with `secret`, valid for the current request only. The use is
+    /// recorded in memory, `spawn_last_used_flush` writes it to disk.
+    pub fn authenticate(
+        &self,
+        secret: &str,
+        issuer: &str,
+        audience: &str,
+    ) -> Result<Claims, String> {
+        let id = secret
+            .strip_prefix(API_KEY_PREFIX)
+            .and_then(|rest| rest.split_once('_'))
+            .map(|(id, _)| id)
+            .ok_or("Malformed API key")?;
+        let now = Utc::now();
+        let mut keys = self.keys.lock().unwrap();
+        let stored = keys
+            .iter_mut()
+            .find(|k| k.key.id == id)
+            .ok_or("Unknown API key")?;
+        if !constant_time_eq(stored.key_hash.as_bytes(), token_hash(secret).as_bytes()) {
+            return Err(format!("Wrong secret for API key {}", id));
+        }
+        if stored.key.revoked_at.is_some() {
+            return Err(format!("API key {} is revoked", id));
+        }
+        if stored.key.expires_at.is_some_and(|at| at <= now) {
+            return Err(format!("API key {} has expired", id));
+        }
+
+        stored.key.last_used_at = Some(now);
+        self.unsaved_use.store(true, Ordering::Relaxed);
+        let claims = Claims::new(
+            format!("api-key:{}", id),
+            (now + Duration::minutes(5)).timestamp() as usize,
+            issuer.to_string(),
+            audience.to_string(),
+        )
+        .with_roles(vec![stored.key.role])
+        .with_issued(None, id.to_string());
+        debug!("Request with API key {}", id);
+        Ok(claims)
+    }
+
+    // Write the keys as they are once it's our turn to write
+    fn save(&self) -> Result<(), APIError> {
+        let _writing = self.write_lock.lock().unwrap();
+        let keys = self.keys.lock().unwrap().clone();
+        write_json_file(&self.path, &keys).map_err(|e| {
+            error!(
+                "Failed to write API key store {}: {}",
+                self.path.display(),
+                e
+            );
+            APIError::new("Failed to save the API key store")
+        })
+    }
+}
+
+/// Write the last use of the API keys every minute, off the request path.
+pub fn spawn_last_used_flush(keys: Arc<ApiKeyStore>) {
+    actix_web::rt::spawn(async move {
+        let mut interval =
+            actix_web::rt::time::interval(std::time::Duration::from_secs(LAST_USED_FLUSH_SECS));
+        loop {
+            interval.tick().await;
+            if !keys.unsaved_use.swap(false, Ordering::Relaxed) {
+                continue;
+            }
+            let store = keys.clone();
+            let saved = match actix_web::web::block(move || store.save()).await {
+                Ok(saved) => saved,
+                Err(e) => Err(APIError::new(&e.to_string())),
+            };
+            if let Err(e) = saved {
+                error!("Failed to record use of API keys: {}", e);
+                // try again next time
+                keys.unsaved_use.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
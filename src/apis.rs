@@ -1,19 +1,24 @@
-use std::collections::HashMap;
 use std::sync::Arc;
 
+use actix_web::http::header::{self, ContentType};
 use actix_web::web::Json;
-use actix_web::{web, HttpResponse, Responder};
-use jsonwebtoken::EncodingKey;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 use log::{debug, error};
 
-use crate::data_service::post_login;
-use crate::data_state::AppState;
+use crate::api_keys::ApiKey;
+use crate::authorization::Role;
+use crate::data_state::{AppState, ReloadStatus};
 use crate::entities::{
-    APIError, APIResponse, Claims, JwtResponse, SearchKafkaRequest, SearchKafkaResponse, UserLogin,
+    APIError, APIPagedResponse, APIResponse, AppDetail, Claims, CreateApiKeyRequest, CreatedApiKey,
+    ErrorCode, ImpactRequest, JwtResponse, LineageQuery, LoginMethods, LogoutRequest, OidcCallback,
+    RefreshTokenRequest, RenderQuery, RevokeTokensRequest, SearchKafkaRequest, TopicDetail,
+    UserLogin,
 };
-use crate::entities_ai::{AISearchResultValue, OpenAICompletionResult};
-use crate::export::export_mm_file;
-use crate::{data_service, entities};
+use crate::drift_report::{drift_report, DriftReport};
+use crate::export::exporter;
+use crate::health_report::{health_report, HealthReport};
+use crate::lineage::{ImpactReport, Lineage, NodeKind, LINEAGE_DEFAULT_DEPTH, LINEAGE_MAX_DEPTH};
+use crate::{data_reload, data_service, entities};
 
 type APIWebResponse<T> = Result<APIResponse<T>, APIError>;
 
@@ -24,60 +29,264 @@ pub async fn login(
     debug!("Logging in");
     debug!("User: {}", user_login.username);
 
-    if let Some(ds) = &data.user_authentication {
-        debug!("User authentication dataset: {:?}", ds);
-
-        let result = post_login(ds, &user_login.username, &user_login.password);
-
-        if let Ok(b) = result {
-            return if b {
-                let expiration = chrono::Utc::now()
-                    .checked_add_signed(chrono::Duration::seconds(3600))
-                    .expect("valid timestamp")
-                    .timestamp();
-
-                let claims = Claims::new(
-                    user_login.username.clone(),
-                    expiration as usize,
-                    "kafka-repo-iss".to_string(),
-                    "kafka-repo-service-aud".to_string(),
-                );
-
-                let jwt_token = jsonwebtoken::encode(
-                    &jsonwebtoken::Header::default(),
-                    &claims,
-                    &EncodingKey::from_secret(data.jwt_secret.as_ref()),
-                )
-                .map_err(|e| {
-                    debug!("Failed to encode jwt token: {}", e);
-                    APIError::new("Failed to encode jwt token")
-                })?;
-
-                let response = JwtResponse {
-                    token: jwt_token,
-                    token_type: "Bearer".to_string(),
-                    expires_in: 3600,
-                };
-
-                Ok(APIResponse { data: response })
-            } else {
-                Err(APIError::new("Invalid username or password"))
-            };
-        } else {
-            let err = result.err().unwrap();
-            return Err(err);
+    let provider = data.auth_provider.clone().ok_or_else(|| {
+        APIError::with_code(
+            ErrorCode::InvalidRequest,
+            "Password login is disabled, sign in with OIDC",
+        )
+    })?;
+    let name = provider.name().to_string();
+    let UserLogin { username, password } = user_login.into_inner();
+    // password hashing is slow on purpose, keep it off the async workers
+    let user = web::block(move || provider.authenticate(&username, &password))
+        .await
+        .map_err(|e| {
+            error!("Failed to run authentication: {}", e);
+            APIError::new("Failed to login")
+        })?
+        .map_err(|e| {
+            debug!("Login rejected by {} provider: {}", name, e);
+            APIError::from(e)
+        })?;
+
+    let refresh_token = data
+        .token_store
+        .issue_refresh_token(&user.user_id, &user.roles)?;
+    token_response(&data, user.user_id, user.roles, refresh_token)
+}
+
+fn token_response(
+    data: &AppState,
+    user_id: String,
+    roles: Vec<Role>,
+    refresh_token: String,
+) -> APIWebResponse<JwtResponse> {
+    let keys = &data.signing_keys;
+    let expiration = chrono::Utc::now()
+        .checked_add_signed(keys.access_token_ttl())
+        .expect("valid timestamp")
+        .timestamp();
+
+    let claims = Claims::new(
+        user_id,
+        expiration as usize,
+        keys.issuer().to_string(),
+        keys.audience().to_string(),
+    )
+    .with_roles(roles);
+
+    let jwt_token = keys.sign(&claims)?;
+
+    let response = JwtResponse {
+        token: jwt_token,
+        token_type: "Bearer".to_string(),
+        expires_in: keys.access_token_ttl().num_seconds() as usize,
+        refresh_token: Some(refresh_token),
+        refresh_expires_in: Some(data.token_store.refresh_ttl().num_seconds() as usize),
+    };
+
+    Ok(APIResponse { data: response })
+}
+
+pub async fn post_refresh_token(
+    data: web::Data<Arc<AppState>>,
+    request: Json<RefreshTokenRequest>,
+) -> APIWebResponse<JwtResponse> {
+    debug!("Refreshing access token");
+    let rotated = data
+        .token_store
+        .rotate_refresh_token(&request.refresh_token)?;
+    token_response(&data, rotated.user_id, rotated.roles, rotated.refresh_token)
+}
+
+/// Revoke the caller's access token, if it sent one, and its refresh token family.
+pub async fn post_logout(
+    data: web::Data<Arc<AppState>>,
+    req: HttpRequest,
+    request: Json<LogoutRequest>,
+) -> APIWebResponse<String> {
+    debug!("Logging out");
+    if let Some(token) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+    {
+        match data.signing_keys.verify(token, false) {
+            Ok(claims) => data.token_store.revoke_jti(claims.jti(), claims.exp())?,
+            Err(e) => debug!("Not revoking the access token on logout: {}", e),
+        }
+    }
+    if let Some(refresh_token) = &request.refresh_token {
+        data.token_store.revoke_refresh_token(refresh_token)?;
+    }
+    Ok(APIResponse {
+        data: "Logged out".to_string(),
+    })
+}
+
+pub async fn post_revoke_tokens(
+    data: web::Data<Arc<AppState>>,
+    request: Json<RevokeTokensRequest>,
+) -> APIWebResponse<String> {
+    debug!("Revoking tokens: {:?}", request);
+    match (&request.jti, &request.user_id) {
+        (Some(jti), _) => {
+            // without the expiry keep it as long as an access token can live
+            let exp = request.exp.unwrap_or(
+                (chrono::Utc::now() + data.signing_keys.access_token_ttl()).timestamp() as usize,
+            );
+            data.token_store.revoke_jti(jti, exp)?;
+            if let Some(user_id) = &request.user_id {
+                data.token_store.revoke_subject(user_id)?;
+            }
+        }
+        (None, Some(user_id)) => data.token_store.revoke_subject(user_id)?,
+        (None, None) => {
+            return Err(APIError::with_code(
+                ErrorCode::InvalidRequest,
+                "jti or user_id is required",
+            ))
+        }
+    }
+    Ok(APIResponse {
+        data: "Revoked".to_string(),
+    })
+}
+
+pub async fn post_api_key(
+    data: web::Data<Arc<AppState>>,
+    req: HttpRequest,
+    request: Json<CreateApiKeyRequest>,
+) -> APIWebResponse<CreatedApiKey> {
+    debug!("Creating API key {}", request.name);
+    if request.name.trim().is_empty() {
+        return Err(APIError::with_code(
+            ErrorCode::InvalidRequest,
+            "API key name is required",
+        ));
+    }
+    let created_by = req
+        .extensions()
+        .get::<Claims>()
+        .map(|c| c.sub().to_string())
+        .unwrap_or_default();
+    let expires_in = match request.expires_in_days {
+        Some(days) if days <= 0 => {
+            return Err(APIError::with_code(
+                ErrorCode::InvalidRequest,
+                "expires_in_days must be positive",
+            ))
         }
+        Some(days) => Some(chrono::Duration::days(days)),
+        None => None,
+    };
+    let (api_key, secret) = data.api_keys.create(
+        request.name.trim(),
+        request.role.unwrap_or(Role::Viewer),
+        &created_by,
+        expires_in,
+    )?;
+    Ok(APIResponse {
+        data: CreatedApiKey { api_key, secret },
+    })
+}
+
+pub async fn get_api_keys(data: web::Data<Arc<AppState>>) -> APIWebResponse<Vec<ApiKey>> {
+    debug!("Listing API keys");
+    Ok(APIResponse {
+        data: data.api_keys.list(),
+    })
+}
+
+pub async fn post_revoke_api_key(
+    data: web::Data<Arc<AppState>>,
+    id: web::Path<String>,
+) -> APIWebResponse<ApiKey> {
+    debug!("Revoking API key {}", id);
+    let api_key = data.api_keys.revoke(&id)?;
+    Ok(APIResponse { data: api_key })
+}
+
+/// Public keys of the tokens issued here, for other services to verify them.
+pub async fn get_jwks(data: web::Data<Arc<AppState>>) -> HttpResponse {
+    HttpResponse::Ok().json(data.signing_keys.jwks())
+}
+
+pub async fn get_login_methods(data: web::Data<Arc<AppState>>) -> APIWebResponse<LoginMethods> {
+    Ok(APIResponse {
+        data: LoginMethods {
+            password: data.auth_provider.is_some(),
+            oidc: data.oidc.is_some(),
+        },
+    })
+}
+
+pub async fn get_oidc_login(data: web::Data<Arc<AppState>>) -> Result<HttpResponse, APIError> {
+    let oidc = data.oidc.as_ref().ok_or_else(|| {
+        APIError::with_code(ErrorCode::InvalidRequest, "OIDC login is not configured")
+    })?;
+    let url = oidc.authorization_url()?;
+    debug!("Redirecting to OIDC issuer {}", oidc.issuer());
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish())
+}
+
+/// Redirect target of the IdP: hands the ID token to the browser the same way login.html stores
+/// a password login token.
+pub async fn get_oidc_callback(
+    data: web::Data<Arc<AppState>>,
+    callback: web::Query<OidcCallback>,
+) -> Result<HttpResponse, APIError> {
+    let oidc = data.oidc.as_ref().ok_or_else(|| {
+        APIError::with_code(ErrorCode::InvalidRequest, "OIDC login is not configured")
+    })?;
+    if let Some(e) = &callback.error {
+        error!(
+            "OIDC login failed: {} {}",
+            e,
+            callback.error_description.as_deref().unwrap_or_default()
+        );
+        return Err(APIError::with_code(
+            ErrorCode::Unauthorized,
+            &format!("Login at the identity provider failed: {}", e),
+        ));
     }
-    Err(APIError::new("Failed to login"))
+    let (Some(code), Some(state)) = (&callback.code, &callback.state) else {
+        return Err(APIError::with_code(
+            ErrorCode::InvalidRequest,
+            "Missing code or state in the OIDC callback",
+        ));
+    };
+
+    let login = oidc.finish_login(code, state).await?;
+    debug!("OIDC login for {}", login.claims.sub());
+    let expires_in = (login.claims.exp() as i64 - chrono::Utc::now().timestamp()).max(0);
+    // a JWT is base64url and dots, nothing in it needs escaping inside a script string
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html><head><meta charset="UTF-8"><title>Signing in</title></head>
+<body><script>
+    localStorage.setItem('expirationTime', new Date().getTime() + {} * 1000);
+    localStorage.setItem('token', '{}');
+    window.location.replace('/index.html');
+</script></body></html>"#,
+        expires_in, login.id_token
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page))
 }
 
 pub async fn get_apps(data: web::Data<Arc<AppState>>) -> APIWebResponse<Vec<String>> {
     debug!("Getting app list");
-    if let Some(ds) = &data.kafka_inventory {
+    let datasets = data.datasets();
+    if let Some(ds) = &datasets.kafka_inventory {
         let apps = data_service::get_app_list(ds)?;
         return Ok(APIResponse { data: apps });
     }
-    Err(APIError::new("Failed to get app list"))
+    Err(inventory_not_loaded())
 }
 
 pub async fn get_topics(
@@ -85,61 +294,207 @@ pub async fn get_topics(
     app_name: web::Path<String>,
 ) -> APIWebResponse<Vec<String>> {
     debug!("Getting topic list for app: {}", app_name);
-    if let Some(ds) = &data.kafka_inventory {
+    let datasets = data.datasets();
+    if let Some(ds) = &datasets.kafka_inventory {
         let topics = data_service::get_topic_list(ds, &app_name)?;
+        // every app in the inventory owns at least one topic
+        if topics.is_empty() {
+            return Err(APIError::with_code(
+                ErrorCode::NotFound,
+                &format!("No app {}", app_name),
+            ));
+        }
         return Ok(APIResponse { data: topics });
     }
-    Err(APIError::new("Failed to get app list"))
+    Err(inventory_not_loaded())
+}
+
+pub async fn get_app_detail(
+    data: web::Data<Arc<AppState>>,
+    app_name: web::Path<String>,
+) -> APIWebResponse<AppDetail> {
+    debug!("Getting details of app: {}", app_name);
+    let datasets = data.datasets();
+    if let (Some(ds_inventory), Some(ds_consumer)) =
+        (&datasets.kafka_inventory, &datasets.kafka_consumer)
+    {
+        let detail = data_service::get_app_detail(ds_inventory, ds_consumer, &app_name)?;
+        return Ok(APIResponse { data: detail });
+    }
+    Err(inventory_not_loaded())
+}
+
+pub async fn get_topic_detail(
+    data: web::Data<Arc<AppState>>,
+    topic_name: web::Path<String>,
+) -> APIWebResponse<TopicDetail> {
+    debug!("Getting details of topic: {}", topic_name);
+    let datasets = data.datasets();
+    if let (Some(ds_inventory), Some(ds_consumer)) =
+        (&datasets.kafka_inventory, &datasets.kafka_consumer)
+    {
+        let detail = data_service::get_topic_detail(
+            ds_inventory,
+            ds_consumer,
+            &data.mq_topics,
+            &topic_name,
+        )?;
+        return Ok(APIResponse { data: detail });
+    }
+    Err(inventory_not_loaded())
+}
+
+pub async fn get_lineage(
+    data: web::Data<Arc<AppState>>,
+    app_name: web::Path<String>,
+    query: web::Query<LineageQuery>,
+) -> APIWebResponse<Lineage> {
+    debug!("Getting lineage of app: {} {:?}", app_name, query);
+    let datasets = data.datasets();
+    if datasets.kafka_inventory.is_none() || datasets.kafka_consumer.is_none() {
+        return Err(inventory_not_loaded());
+    }
+    let lineage = datasets.lineage.lineage(
+        &app_name,
+        query.direction.unwrap_or_default(),
+        query.depth.unwrap_or(LINEAGE_DEFAULT_DEPTH),
+    )?;
+    Ok(APIResponse { data: lineage })
+}
+
+pub async fn post_impact(
+    data: web::Data<Arc<AppState>>,
+    impact_request: Json<ImpactRequest>,
+) -> APIWebResponse<ImpactReport> {
+    debug!("Getting impact of: {:?}", impact_request);
+    let (kind, root) = match (&impact_request.topic_name, &impact_request.app_name) {
+        (Some(topic), None) => (NodeKind::Topic, topic),
+        (None, Some(app)) => (NodeKind::App, app),
+        _ => {
+            return Err(APIError::with_code(
+                ErrorCode::InvalidRequest,
+                "Set either topic_name or app_name",
+            ))
+        }
+    };
+    let datasets = data.datasets();
+    if datasets.kafka_inventory.is_none() || datasets.kafka_consumer.is_none() {
+        return Err(inventory_not_loaded());
+    }
+    let report = datasets.lineage.impact(
+        kind,
+        root,
+        impact_request.max_depth.unwrap_or(LINEAGE_MAX_DEPTH),
+    )?;
+    Ok(APIResponse { data: report })
 }
 
 pub async fn get_consumers(data: web::Data<Arc<AppState>>) -> APIWebResponse<Vec<String>> {
     debug!("Getting consumer list");
-    if let Some(ds) = &data.kafka_consumer {
+    let datasets = data.datasets();
+    if let Some(ds) = &datasets.kafka_consumer {
         let consumers = data_service::get_consumer_list(ds)?;
         return Ok(APIResponse { data: consumers });
     }
-    Err(APIError::new("Failed to get consumer list"))
+    Err(inventory_not_loaded())
 }
 
 pub async fn post_search_kafka(
     data: web::Data<Arc<AppState>>,
     search_request: Json<entities::SearchKafkaRequest>,
-) -> APIWebResponse<Vec<SearchKafkaResponse>> {
+) -> Result<APIPagedResponse<serde_json::Value>, APIError> {
     debug!("Searching kafka with request: {:?}", search_request);
-    if let (Some(ds_inventory), Some(ds_consumer)) = (&data.kafka_inventory, &data.kafka_consumer) {
-        let result = data_service::search(ds_inventory, ds_consumer, &search_request)?;
-        return Ok(APIResponse { data: result });
+    let datasets = data.datasets();
+    if let (Some(ds_inventory), Some(ds_consumer)) =
+        (&datasets.kafka_inventory, &datasets.kafka_consumer)
+    {
+        let page = data_service::search_page(
+            ds_inventory,
+            ds_consumer,
+            &datasets.text_index,
+            &search_request,
+        )?;
+        return Ok(APIPagedResponse {
+            data: page.rows,
+            total: page.total,
+            offset: page.offset,
+            limit: page.limit,
+            next_cursor: page.next_cursor,
+            facets: page.facets,
+        });
     }
-    Err(APIError::new("Failed to search kafka"))
+    Err(inventory_not_loaded())
 }
 
-fn split_questions_and_non_questions(input: &str) -> (Vec<String>, Vec<String>) {
-    // Split the input string into two parts: Questions and Non-Questions
-    let parts: Vec<&str> = input.split("**Non-Questions:**").collect();
-
-    let mut questions = Vec::new();
-    let mut non_questions = Vec::new();
-
-    if parts.len() == 2 {
-        // Split the questions part by "**Questions:**" to remove the label
-        if let Some(question_part) = parts[0].split("**Questions:**").nth(1) {
-            questions = question_part
-                .lines() // Split by lines instead of '\n' to handle all line endings
-                .filter(|line| !line.trim().is_empty()) // Filter out empty lines
-                .map(|line| line.trim_start_matches("1.").trim().to_string())
-                .collect();
-        }
+pub async fn get_reload_status(data: web::Data<Arc<AppState>>) -> APIWebResponse<ReloadStatus> {
+    debug!("Getting dataset reload status");
+    let status = data.reload_status.read().unwrap().clone();
+    Ok(APIResponse { data: status })
+}
+
+pub async fn post_reload(data: web::Data<Arc<AppState>>) -> APIWebResponse<ReloadStatus> {
+    debug!("Reloading datasets");
+    let status = data_reload::reload(&data).await;
+    Ok(APIResponse { data: status })
+}
+
+pub async fn get_drift_report(data: web::Data<Arc<AppState>>) -> APIWebResponse<DriftReport> {
+    debug!("Getting drift report");
+    let datasets = data.datasets();
+    if let (Some(ds_inventory), Some(ds_consumer)) =
+        (&datasets.kafka_inventory, &datasets.kafka_consumer)
+    {
+        let snapshot = datasets.cluster_snapshot.as_ref().ok_or_else(|| {
+            APIError::with_code(
+                ErrorCode::DataUnavailable,
+                "No kafka cluster data, set KAFKA_CLUSTER to get a drift report",
+            )
+        })?;
+        let report = drift_report(ds_inventory, ds_consumer, snapshot)?;
+        return Ok(APIResponse { data: report });
+    }
+    Err(inventory_not_loaded())
+}
 
-        // Process the non-questions part
-        non_questions = parts[1]
-            .lines() // Split by lines instead of '\n' to handle all line endings
-            .filter(|line| !line.trim().is_empty()) // Filter out empty lines
-            .map(|line| line.trim_start_matches("1.").trim().to_string())
-            .collect();
+pub async fn get_health_report(data: web::Data<Arc<AppState>>) -> APIWebResponse<HealthReport> {
+    debug!("Getting health report");
+    let datasets = data.datasets();
+    if let (Some(ds_inventory), Some(ds_consumer)) =
+        (&datasets.kafka_inventory, &datasets.kafka_consumer)
+    {
+        let report = health_report(ds_inventory, ds_consumer, &data.naming_rules)?;
+        return Ok(APIResponse { data: report });
     }
+    Err(inventory_not_loaded())
+}
+
+pub async fn get_health_report_csv(
+    data: web::Data<Arc<AppState>>,
+) -> Result<HttpResponse, APIError> {
+    debug!("Getting health report as CSV");
+    let datasets = data.datasets();
+    if let (Some(ds_inventory), Some(ds_consumer)) =
+        (&datasets.kafka_inventory, &datasets.kafka_consumer)
+    {
+        let report = health_report(ds_inventory, ds_consumer, &data.naming_rules)?;
+        return Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"kafka_health_report.csv\"",
+            ))
+            .body(report.to_csv()?));
+    }
+    Err(inventory_not_loaded())
+}
 
-    (questions, non_questions)
+fn inventory_not_loaded() -> APIError {
+    APIError::with_code(
+        ErrorCode::DataUnavailable,
+        "The kafka inventory and consumer datasets are not loaded",
+    )
 }
+
 /// Perform AI search using Azure AI and Open AI Completion.
 ///
 /// # Arguments
@@ -164,7 +519,7 @@ pub async fn post_ai_search(
 
         let mut knowledge = String::new();
         knowledge.push_str(pre_knowledge);
-        knowledge.push_str("\n");
+        knowledge.push('\n');
         // find more knowledge from AI search
         let mut array_of_filters = Vec::new();
         if let Some(app_owner) = &search_request.app_owner {
@@ -187,11 +542,11 @@ pub async fn post_ai_search(
         if !question.is_empty() {
             question.push_str(" and  ");
             question.push_str("( ");
-            question.push_str(&query_message);
+            question.push_str(query_message);
             question.push_str(") ");
         }else{
             question.push_str("( ");
-            question.push_str(&query_message);
+            question.push_str(query_message);
             question.push_str(") ");
         }
 
@@ -213,7 +568,7 @@ pub async fn post_ai_search(
                             &app_state,
                         ).await.map_err(|e| {
                             error!("Failed to search AI: {}", e);
-                            APIError::new("Failed to search AI")
+                            APIError::with_code(e.code(), "Failed to search AI")
                         })?;
                         //append knowledge from AI search
                         debug!("Result from AI Search: {:#?}", result);
@@ -246,48 +601,42 @@ pub async fn post_ai_search(
         debug!("Knowledge: {:#?}", knowledge);
 
         let result =
-            crate::azure_ai_apis::open_ai_completion(&query_message, &knowledge, &app_state).await?;
+            crate::azure_ai_apis::open_ai_completion(query_message, &knowledge, &app_state).await?;
         debug!("Result from Open AI Completion: {:#?}", result);
 
         Ok(APIResponse { data: result })
     } else {
-        Err(APIError::new(
+        Err(APIError::with_code(
+            ErrorCode::InvalidRequest,
             "Failed to search AI , Please provide query message",
         ))
     }
 }
 
-fn sort_ai_search_result_by_score_get_n_top(
-    mut value: Vec<AISearchResultValue>,
-    n: usize,
-) -> Vec<AISearchResultValue> {
-    value.sort_by(|a, b| {
-        b.search_score
-            .unwrap_or(0.0)
-            .partial_cmp(&a.search_score.unwrap_or(0.0))
-            .unwrap()
-    });
-    // get top n after sorted by score
-    value[0..std::cmp::min(n, value.len())].to_vec()
-}
 pub async fn post_topic_kafka_relation_render(
     data: web::Data<Arc<AppState>>,
     search_request: Json<SearchKafkaRequest>,
+    query: web::Query<RenderQuery>,
 ) -> Result<impl Responder, APIError> {
-    debug!("Searching kafka with request: {:?}", search_request);
-    if let (Some(ds_inventory), Some(ds_consumer)) = (&data.kafka_inventory, &data.kafka_consumer) {
-        let result = data_service::search(ds_inventory, ds_consumer, &search_request)?;
-        // Export to mermaid file
-        let path = "flowchart.mmd";
-        let mermaid_text = export_mm_file(result.clone(), path).map_err(|e| {
-            debug!("Failed to export to mermaid file: {}", e);
-            APIError::new("Failed to export to mermaid file")
-        })?;
-
+    debug!(
+        "Rendering kafka search as {:?}: {:?}",
+        query.format, search_request
+    );
+    let datasets = data.datasets();
+    if let (Some(ds_inventory), Some(ds_consumer)) =
+        (&datasets.kafka_inventory, &datasets.kafka_consumer)
+    {
+        let result = data_service::search(
+            ds_inventory,
+            ds_consumer,
+            &datasets.text_index,
+            &search_request,
+        )?;
+        let exporter = exporter(query.format.unwrap_or_default());
         let r = HttpResponse::Ok()
-            .content_type("text/plain")
-            .body(mermaid_text);
+            .content_type(exporter.content_type())
+            .body(exporter.export(&result)?);
         return Ok(r);
     }
-    Err(APIError::new("Failed to search kafka"))
+    Err(inventory_not_loaded())
 }
@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, error, info};
+use polars::prelude::*;
+
+use crate::authorization::{parse_roles, Role};
+use crate::data_format::DatasetFormat;
+use crate::data_source::{load_frame, source_from_uri, BlobDefaults};
+use crate::dataset_schema::string_values;
+use crate::entities::{APIError, ErrorCode};
+
+pub const COL_USER_ID: &str = "User_ID";
+pub const COL_PASSWORD_HASH: &str = "Password_Hash";
+// Optional, `;` separated roles; users without any are viewers
+pub const COL_ROLES: &str = "Roles";
+// Column of the old clear text credential files
+const COL_PASSWORD_PLAINTEXT: &str = "Password";
+
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: String,
+    pub roles: Vec<Role>,
+}
+
+#[derive(Debug, derive_more::Display)]
+pub enum AuthFailure {
+    #[display("Invalid username or password")]
+    InvalidCredentials,
+    #[display("Too many failed logins, account locked until {_0}")]
+    Locked(DateTime<Utc>),
+}
+
+impl From<AuthFailure> for APIError {
+    fn from(failure: AuthFailure) -> Self {
+        let code = match failure {
+            AuthFailure::InvalidCredentials => ErrorCode::Unauthorized,
+            AuthFailure::Locked(_) => ErrorCode::AccountLocked,
+        };
+        APIError::with_code(code, &failure.to_string())
+    }
+}
+
+/// Checks a username and password. Implementations may block (hashing, directory lookups),
+/// so callers on the async runtime should run them with `web::block`.
+pub trait AuthProvider: Send + Sync {
+    /// Short name for logs
+    fn name(&self) -> &str;
+    fn authenticate(&self, username: &str, password: &str)
+        -> Result<AuthenticatedUser, AuthFailure>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Argon2,
+    Bcrypt,
+}
+
+impl HashAlgorithm {
+    fn of(hash: &str) -> Option<HashAlgorithm> {
+        if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+        {
+            return Some(HashAlgorithm::Bcrypt);
+        }
+        match PasswordHash::new(hash) {
+            Ok(parsed) if parsed.algorithm.as_str().starts_with("argon2") => {
+                Some(HashAlgorithm::Argon2)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Hash a password for the credentials file.
+pub fn hash_password(password: &str, algorithm: HashAlgorithm) -> Result<String, APIError> {
+    match algorithm {
+        HashAlgorithm::Argon2 => {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|e| APIError::new(&format!("Failed to hash password: {}", e)))
+        }
+        HashAlgorithm::Bcrypt => bcrypt::hash(password, bcrypt::DEFAULT_COST)
+            .map_err(|e| APIError::new(&format!("Failed to hash password: {}", e))),
+    }
+}
+
+// Both libraries compare the derived hash in constant time.
+fn verify_password(password: &str, hash: &str) -> bool {
+    match HashAlgorithm::of(hash) {
+        Some(HashAlgorithm::Bcrypt) => bcrypt::verify(password, hash).unwrap_or(false),
+        Some(HashAlgorithm::Argon2) => match PasswordHash::new(hash) {
+            Ok(parsed) => Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok(),
+            Err(_) => false,
+        },
+        None => false,
+    }
+}
+
+struct FileUser {
+    password_hash: String,
+    roles: Vec<Role>,
+}
+
+/// Users, password hashes and roles from the `User_ID` / `Password_Hash` / `Roles` columns of a dataset.
+pub struct FileAuthProvider {
+    users: HashMap<String, FileUser>,
+    // Verified for unknown users so that they take as long to reject as a wrong password
+    dummy_hash: String,
+}
+
+impl FileAuthProvider {
+    pub fn from_frame(ds: &DataFrame) -> Result<FileAuthProvider, APIError> {
+        if ds.column(COL_PASSWORD_HASH).is_err() {
+            return Err(if ds.column(COL_PASSWORD_PLAINTEXT).is_ok() {
+                APIError::new(&format!(
+                    "The user authentication dataset has a clear text {} column, replace it with {} hashes from `kafka-repo hash-password`",
+                    COL_PASSWORD_PLAINTEXT, COL_PASSWORD_HASH
+                ))
+            } else {
+                APIError::new(&format!(
+                    "The user authentication dataset has no {} column",
+                    COL_PASSWORD_HASH
+                ))
+            });
+        }
+        let user_ids = string_values(ds, COL_USER_ID)?;
+        let hashes = string_values(ds, COL_PASSWORD_HASH)?;
+        let roles = if ds.column(COL_ROLES).is_ok() {
+            string_values(ds, COL_ROLES)?
+        } else {
+            vec![None; ds.height()]
+        };
+
+        let mut users = HashMap::new();
+        let mut invalid = Vec::new();
+        let mut algorithms = Vec::new();
+        for ((user_id, hash), user_roles) in user_ids.into_iter().zip(hashes).zip(roles) {
+            let (Some(user_id), Some(hash)) = (user_id, hash) else {
+                continue;
+            };
+            let mut user_roles = parse_roles(user_roles.as_deref().unwrap_or_default())
+                .map_err(|e| APIError::new(&format!("{} of user {}: {}", COL_ROLES, user_id, e)))?;
+            if user_roles.is_empty() {
+                user_roles.push(Role::Viewer);
+            }
+            let hash = hash.trim().to_string();
+            match HashAlgorithm::of(&hash) {
+                Some(algorithm) => {
+                    algorithms.push(algorithm);
+                    users.insert(
+                        user_id,
+                        FileUser {
+                            password_hash: hash,
+                            roles: user_roles,
+                        },
+                    );
+                }
+                None => invalid.push(user_id),
+            }
+        }
+        if !invalid.is_empty() {
+            return Err(APIError::new(&format!(
+                "{} is not an Argon2 or bcrypt hash for {} user(s)",
+                COL_PASSWORD_HASH,
+                invalid.len()
+            ))
+            .with_details(Some(serde_json::json!({ "users": invalid }))));
+        }
+
+        let dummy_algorithm = if !algorithms.is_empty()
+            && algorithms.iter().all(|a| *a == HashAlgorithm::Bcrypt)
+        {
+            HashAlgorithm::Bcrypt
+        } else {
+            HashAlgorithm::Argon2
+        };
+        let dummy_hash = hash_password("not a password", dummy_algorithm)?;
+        info!("Loaded {} users for file authentication", users.len());
+        Ok(FileAuthProvider { users, dummy_hash })
+    }
+}
+
+impl AuthProvider for FileAuthProvider {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<AuthenticatedUser, AuthFailure> {
+        match self.users.get(username) {
+            Some(user) if verify_password(password, &user.password_hash) => {
+                Ok(AuthenticatedUser {
+                    user_id: username.to_string(),
+                    roles: user.roles.clone(),
+                })
+            }
+            Some(_) => Err(AuthFailure::InvalidCredentials),
+            None => {
+                verify_password(password, &self.dummy_hash);
+                Err(AuthFailure::InvalidCredentials)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LockoutPolicy {
+    pub max_failures: u32,
+    pub lockout: Duration,
+}
+
+#[derive(Debug)]
+struct FailedLogins {
+    // failed and still running attempts
+    count: u32,
+    last_attempt: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl FailedLogins {
+    // forgotten once the lockout is over, or a lockout period after the last attempt
+    fn expired(&self, now: DateTime<Utc>, lockout: Duration) -> bool {
+        match self.locked_until {
+            Some(until) => until <= now,
+            None => self.last_attempt + lockout <= now,
+        }
+    }
+}
+
+/// Locks a username out for `policy.lockout` after `policy.max_failures` failed logins in a row,
+/// in front of any other provider. An attempt counts as failed until it succeeds, so concurrent
+/// guesses can't get past the limit.
+pub struct LockoutProvider {
+    inner: Box<dyn AuthProvider>,
+    policy: LockoutPolicy,
+    failures: Mutex<HashMap<String, FailedLogins>>,
+}
+
+impl LockoutProvider {
+    pub fn new(inner: Box<dyn AuthProvider>, policy: LockoutPolicy) -> Self {
+        LockoutProvider {
+            inner,
+            policy,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Count the attempt before the password is checked, or refuse it if the account is locked
+    fn begin_attempt(&self, username: &str, now: DateTime<Utc>) -> Result<(), AuthFailure> {
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, f| !f.expired(now, self.policy.lockout));
+        let entry = failures
+            .entry(username.to_string())
+            .or_insert(FailedLogins {
+                count: 0,
+                last_attempt: now,
+                locked_until: None,
+            });
+        if let Some(until) = entry.locked_until {
+            debug!("Login for locked account {}", username);
+            return Err(AuthFailure::Locked(until));
+        }
+        if entry.count >= self.policy.max_failures {
+            // the attempts still running may all fail
+            let until = now + self.policy.lockout;
+            debug!(
+                "Login for {} with {} attempts running",
+                username, entry.count
+            );
+            return Err(AuthFailure::Locked(until));
+        }
+        entry.count += 1;
+        entry.last_attempt = now;
+        Ok(())
+    }
+
+    fn end_attempt(&self, username: &str, result: &Result<AuthenticatedUser, AuthFailure>) {
+        let mut failures = self.failures.lock().unwrap();
+        match result {
+            Ok(_) => {
+                failures.remove(username);
+            }
+            Err(AuthFailure::InvalidCredentials) => {
+                let Some(entry) = failures.get_mut(username) else {
+                    return;
+                };
+                if entry.count >= self.policy.max_failures && entry.locked_until.is_none() {
+                    let until = Utc::now() + self.policy.lockout;
+                    error!(
+                        "Locking {} until {} after {} failed logins",
+                        username, until, entry.count
+                    );
+                    entry.locked_until = Some(until);
+                }
+            }
+            // the password wasn't checked
+            Err(_) => {
+                if let Some(entry) = failures.get_mut(username) {
+                    entry.count = entry.count.saturating_sub(1);
+                }
+            }
+        }
+    }
+}
+
+impl AuthProvider for LockoutProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<AuthenticatedUser, AuthFailure> {
+        self.begin_attempt(username, Utc::now())?;
+        let result = self.inner.authenticate(username, password);
+        self.end_attempt(username, &result);
+        result
+    }
+}
+
+/// Build the password auth provider from the environment, `None` if password logins are off:
+///
+/// * `USER_AUTHENTICATION_FILE` - dataset with `User_ID` and `Password_Hash` columns
+/// * `AUTH_MAX_FAILED_LOGINS` - failed logins in a row before a lockout, default 5
+/// * `AUTH_LOCKOUT_SECS` - lockout duration, default 900
+pub async fn auth_provider_from_env() -> Result<Option<Arc<dyn AuthProvider>>, APIError> {
+    let location = match std::env::var("USER_AUTHENTICATION_FILE") {
+        Ok(location) if !location.is_empty() => location,
+        _ => return Ok(None),
+    };
+    // there is no local fallback for credentials
+    let source = source_from_uri(&location, &BlobDefaults::from_env(), false)?;
+    let format = DatasetFormat::from_env("USER_AUTHENTICATION", &location)?;
+    let (ds, _) = load_frame(source.as_ref(), &format).await?;
+    let provider = FileAuthProvider::from_frame(&ds)?;
+
+    let max_failures = std::env::var("AUTH_MAX_FAILED_LOGINS")
+        .unwrap_or("5".to_string())
+        .parse::<u32>()
+        .map_err(|_| APIError::new("AUTH_MAX_FAILED_LOGINS must be a number"))?;
+    let lockout_secs = std::env::var("AUTH_LOCKOUT_SECS")
+        .unwrap_or("900".to_string())
+        .parse::<i64>()
+        .map_err(|_| APIError::new("AUTH_LOCKOUT_SECS must be a number of seconds"))?;
+
+    Ok(Some(Arc::new(LockoutProvider::new(
+        Box::new(provider),
+        LockoutPolicy {
+            max_failures: max_failures.max(1),
+            lockout: Duration::seconds(lockout_secs),
+        },
+    ))))
+}
@@ -1,16 +1,19 @@
+use std::time::Duration;
+
 use async_openai::config::AzureConfig;
+use async_openai::error::OpenAIError;
 use async_openai::types::{
-    ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageArgs,
-    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
-    CreateChatCompletionRequestArgs,
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
 };
 use log::debug;
 
 use crate::data_state::AppState;
-use crate::entities::APIError;
-use crate::entities_ai::{
-    AISearchResult, OpenAICompleteRequest, OpenAICompleteRequestMessage, OpenAICompletionResult,
-};
+use crate::entities::{APIError, ErrorCode};
+use crate::entities_ai::AISearchResult;
+
+const AI_SEARCH_TIMEOUT: Duration = Duration::from_secs(30);
+
 /**
  * Performs an AI search using the Azure AI Search service.
  *
@@ -29,7 +32,10 @@ pub async fn ai_search(
 ) -> Result<AISearchResult, APIError> {
     let api_endpoint = app_state.clone().azure_ai_search_url.unwrap();
     let ai_search_key = app_state.clone().azure_ai_search_key.unwrap();
-    let client = reqwest::Client::new();
+    let client = reqwest::Client::builder()
+        .timeout(AI_SEARCH_TIMEOUT)
+        .build()
+        .map_err(|e| APIError::new(&format!("Failed to create AI Search client: {}", e)))?;
     let url = format!(
         "{}/indexes('{}')/docs/search?api-version=2024-05-01-preview",
         api_endpoint, index_name
@@ -52,12 +58,22 @@ pub async fn ai_search(
         ))
         .send()
         .await
-        .map_err(|e| APIError::new(&format!("Failed to send request to AI Search: {}", e)))?;
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| {
+            let code = if e.is_timeout() {
+                ErrorCode::UpstreamAiTimeout
+            } else {
+                ErrorCode::UpstreamAiUnavailable
+            };
+            APIError::with_code(code, &format!("Failed to send request to AI Search: {}", e))
+        })?;
 
-    let r = response
-        .json::<AISearchResult>()
-        .await
-        .map_err(|e| APIError::new(&format!("Failed to parse response from AI Search: {}", e)))?;
+    let r = response.json::<AISearchResult>().await.map_err(|e| {
+        APIError::with_code(
+            ErrorCode::UpstreamAiUnavailable,
+            &format!("Failed to parse response from AI Search: {}", e),
+        )
+    })?;
 
     Ok(r)
 }
@@ -69,15 +85,17 @@ pub async fn ai_search(
  * \return A result containing the OpenAI completion result or an API error.
  */
 pub async fn open_ai_completion(
-    prompt_message: &String,
-    knowledge: &String,
+    prompt_message: &str,
+    knowledge: &str,
     app_state: &AppState,
 ) -> Result<String, APIError> {
     let az_config = app_state.open_ai_config.clone();
 
     let res = process_with_llm(prompt_message, knowledge, &az_config)
         .await
-        .map_err(|e| APIError::new(&format!("Failed to process with LLM: {}", e)))?;
+        .map_err(|e| {
+            APIError::with_code(e.code(), &format!("Failed to process with LLM: {}", e))
+        })?;
 
     debug!("OpenAI Completion Result: {:#?}", res);
     Ok(res)
@@ -122,11 +140,13 @@ async fn process_with_llm(
 
     debug!("Request: {:?}", request);
 
-    let res = client
-        .chat()
-        .create(request)
-        .await
-        .map_err(|e| APIError::new(&format!("Failed to create chat completion: {}", e)))?;
+    let res = client.chat().create(request).await.map_err(|e| {
+        let code = match &e {
+            OpenAIError::Reqwest(e) if e.is_timeout() => ErrorCode::UpstreamAiTimeout,
+            _ => ErrorCode::UpstreamAiUnavailable,
+        };
+        APIError::with_code(code, &format!("Failed to create chat completion: {}", e))
+    })?;
     debug!("Response: {:?}", res);
     let mut text_result = String::new();
     if res.choices.is_empty() {
@@ -0,0 +1,103 @@
+use std::io::{BufRead, Error, ErrorKind};
+
+use crate::auth_provider::{hash_password, HashAlgorithm};
+use crate::data_reload::{dataset_config_from_env, load_datasets};
+use crate::drift_report::drift_report;
+
+const USAGE: &str = "Usage:
+  kafka-repo                 start the server
+  kafka-repo drift [--json]  compare the declared inventory with the kafka cluster (KAFKA_CLUSTER)
+  kafka-repo hash-password [--bcrypt] [<user>]
+                             hash a password read from stdin for USER_AUTHENTICATION_FILE,
+                             printed as a User_ID,Password_Hash row when <user> is given";
+
+// login.html sends the hex SHA-256 of the password, so that is what gets hashed
+fn login_page_digest(password: &str) -> String {
+    ring::digest::digest(&ring::digest::SHA256, password.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Run a command line tool, reading the same environment variables as the server.
+pub async fn run(args: &[String]) -> std::io::Result<()> {
+    match args[0].as_str() {
+        "drift" => drift(args.iter().any(|a| a == "--json")).await,
+        "hash-password" => hash_password_command(&args[1..]),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => {
+            eprintln!("Unknown command {}\n{}", other, USAGE);
+            Err(Error::new(ErrorKind::InvalidInput, "unknown command"))
+        }
+    }
+}
+
+async fn drift(as_json: bool) -> std::io::Result<()> {
+    let config = dataset_config_from_env();
+    if config.kafka_cluster.is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "KAFKA_CLUSTER must be set for a drift report",
+        ));
+    }
+    let (datasets, status) = load_datasets(&config)
+        .await
+        .map_err(|e| Error::other(e.to_string()))?;
+    let error = status.kafka_cluster.and_then(|c| c.error);
+
+    let report = match (
+        &datasets.kafka_inventory,
+        &datasets.kafka_consumer,
+        &datasets.cluster_snapshot,
+    ) {
+        (Some(ds_inventory), Some(ds_consumer), Some(snapshot)) => {
+            drift_report(ds_inventory, ds_consumer, snapshot)
+                .map_err(|e| Error::other(e.to_string()))?
+        }
+        _ => {
+            return Err(Error::other(format!(
+                "Failed to harvest kafka cluster: {}",
+                error.unwrap_or_default()
+            )))
+        }
+    };
+
+    if as_json {
+        let json =
+            serde_json::to_string_pretty(&report).map_err(|e| Error::other(e.to_string()))?;
+        println!("{}", json);
+    } else {
+        print!("{}", report.to_text());
+    }
+    Ok(())
+}
+
+fn hash_password_command(args: &[String]) -> std::io::Result<()> {
+    let algorithm = if args.iter().any(|a| a == "--bcrypt") {
+        HashAlgorithm::Bcrypt
+    } else {
+        HashAlgorithm::Argon2
+    };
+    let user = args.iter().find(|a| !a.starts_with("--"));
+
+    eprintln!("Password:");
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "empty password"));
+    }
+
+    let hash = hash_password(&login_page_digest(password), algorithm)
+        .map_err(|e| Error::other(e.to_string()))?;
+    match user {
+        // quoted, argon2 hashes contain commas
+        Some(user) => println!("{},\"{}\"", user, hash),
+        None => println!("{}", hash),
+    }
+    Ok(())
+}
@@ -0,0 +1,297 @@
+use std::io::Cursor;
+
+use log::debug;
+use polars::prelude::*;
+
+use crate::entities::APIError;
+
+/// Text encoding of a CSV export. Excel on Windows saves "CSV" as Windows-1252 unless
+/// "CSV UTF-8" is chosen, and that one starts with a byte order mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Windows1252,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvFormat {
+    pub delimiter: u8,
+    pub encoding: TextEncoding,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat {
+            delimiter: b',',
+            encoding: TextEncoding::Utf8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatasetFormat {
+    Csv(CsvFormat),
+    Parquet,
+    // a single array of row objects
+    Json,
+    // one row object per line
+    NdJson,
+}
+
+impl DatasetFormat {
+    /// Pick the format from `explicit` (`csv`, `parquet`, `json`, `ndjson` / `jsonl`) or else from
+    /// the extension of `location`, defaulting to CSV. `delimiter` and `encoding` only apply to CSV.
+    pub fn resolve(
+        location: &str,
+        explicit: Option<&str>,
+        delimiter: Option<&str>,
+        encoding: Option<&str>,
+    ) -> Result<DatasetFormat, APIError> {
+        let name = match explicit {
+            Some(format) => format.trim().to_lowercase(),
+            None => {
+                let path = location.split(['?', '#']).next().unwrap_or(location);
+                path.rsplit_once('.')
+                    .map(|(_, ext)| ext.to_lowercase())
+                    .unwrap_or_default()
+            }
+        };
+        let format = match name.as_str() {
+            "parquet" | "pq" => DatasetFormat::Parquet,
+            "json" => DatasetFormat::Json,
+            "ndjson" | "jsonl" => DatasetFormat::NdJson,
+            "csv" | "txt" | "" => DatasetFormat::Csv(CsvFormat {
+                delimiter: parse_delimiter(delimiter)?,
+                encoding: parse_encoding(encoding)?,
+            }),
+            // an unknown extension is most likely still a CSV export, an unknown explicit format is a typo
+            _ if explicit.is_none() => DatasetFormat::Csv(CsvFormat {
+                delimiter: parse_delimiter(delimiter)?,
+                encoding: parse_encoding(encoding)?,
+            }),
+            other => {
+                return Err(APIError::new(&format!(
+                    "Unsupported dataset format {}, expected csv, parquet, json or ndjson",
+                    other
+                )))
+            }
+        };
+        debug!("Dataset format for {}: {:?}", location, format);
+        Ok(format)
+    }
+
+    /// Read the format settings for a dataset from `<PREFIX>_FORMAT`, `<PREFIX>_CSV_DELIMITER`
+    /// and `<PREFIX>_CSV_ENCODING`.
+    pub fn from_env(prefix: &str, location: &str) -> Result<DatasetFormat, APIError> {
+        let format = std::env::var(format!("{}_FORMAT", prefix)).ok();
+        let delimiter = std::env::var(format!("{}_CSV_DELIMITER", prefix)).ok();
+        let encoding = std::env::var(format!("{}_CSV_ENCODING", prefix)).ok();
+        DatasetFormat::resolve(
+            location,
+            format.as_deref(),
+            delimiter.as_deref(),
+            encoding.as_deref(),
+        )
+    }
+}
+
+fn parse_delimiter(delimiter: Option<&str>) -> Result<u8, APIError> {
+    match delimiter {
+        None | Some("") => Ok(b','),
+        Some("\\t") | Some("tab") => Ok(b'\t'),
+        Some(d) if d.len() == 1 => Ok(d.as_bytes()[0]),
+        Some(d) => Err(APIError::new(&format!(
+            "CSV delimiter must be a single character, got {}",
+            d
+        ))),
+    }
+}
+
+fn parse_encoding(encoding: Option<&str>) -> Result<TextEncoding, APIError> {
+    match encoding.map(|e| e.trim().to_lowercase()).as_deref() {
+        None | Some("") | Some("utf8") | Some("utf-8") => Ok(TextEncoding::Utf8),
+        Some("windows-1252") | Some("cp1252") | Some("latin1") | Some("iso-8859-1") => {
+            Ok(TextEncoding::Windows1252)
+        }
+        Some(other) => Err(APIError::new(&format!(
+            "Unsupported CSV encoding {}, expected utf-8 or windows-1252",
+            other
+        ))),
+    }
+}
+
+/// Read raw dataset bytes into a frame.
+pub fn read_frame(bytes: Vec<u8>, format: &DatasetFormat) -> PolarsResult<DataFrame> {
+    match format {
+        DatasetFormat::Csv(csv) => {
+            let data = match csv.encoding {
+                TextEncoding::Utf8 => strip_utf8_bom(bytes),
+                TextEncoding::Windows1252 => decode_windows_1252(&bytes).into_bytes(),
+            };
+            read_csv_bytes(data, csv.delimiter)
+        }
+        DatasetFormat::Parquet => ParquetReader::new(Cursor::new(bytes)).finish(),
+        DatasetFormat::Json => JsonReader::new(Cursor::new(bytes))
+            .with_json_format(JsonFormat::Json)
+            .infer_schema_len(None)
+            .finish(),
+        DatasetFormat::NdJson => JsonLineReader::new(Cursor::new(bytes))
+            .infer_schema_len(None)
+            .finish(),
+    }
+}
+
+pub fn read_csv_bytes(data: Vec<u8>, delimiter: u8) -> PolarsResult<DataFrame> {
+    CsvReadOptions::default()
+        .with_has_header(true)
+        .with_infer_schema_length(None)
+        .map_parse_options(|options| options.with_separator(delimiter))
+        .into_reader_with_file_handle(Cursor::new(data))
+        .finish()
+}
+
+fn strip_utf8_bom(mut bytes: Vec<u8>) -> Vec<u8> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        bytes.drain(..3);
+    }
+    bytes
+}
+
+// Windows-1252 is Latin-1 except for 0x80..=0x9F, which hold punctuation such as smart quotes.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+            _ => b as char,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn csv(delimiter: u8, encoding: TextEncoding) -> DatasetFormat {
+        DatasetFormat::Csv(CsvFormat {
+            delimiter,
+            encoding,
+        })
+    }
+
+    #[test]
+    fn resolve_uses_the_explicit_format_first() {
+        assert_eq!(
+            DatasetFormat::resolve("inventory.csv", Some(" Parquet "), None, None).unwrap(),
+            DatasetFormat::Parquet
+        );
+        assert_eq!(
+            DatasetFormat::resolve("inventory.json", Some("jsonl"), None, None).unwrap(),
+            DatasetFormat::NdJson
+        );
+        let e = DatasetFormat::resolve("inventory.csv", Some("xlsx"), None, None).unwrap_err();
+        assert!(
+            e.to_string().contains("Unsupported dataset format xlsx"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn resolve_falls_back_to_the_extension() {
+        let resolve = |location| DatasetFormat::resolve(location, None, None, None).unwrap();
+        assert_eq!(
+            resolve("az://acct/data/inventory.parquet"),
+            DatasetFormat::Parquet
+        );
+        assert_eq!(resolve("inventory.PQ"), DatasetFormat::Parquet);
+        assert_eq!(
+            resolve("https://example.com/inventory.json?sig=abc"),
+            DatasetFormat::Json
+        );
+        assert_eq!(resolve("inventory.ndjson#rows"), DatasetFormat::NdJson);
+        assert_eq!(resolve("inventory.jsonl"), DatasetFormat::NdJson);
+        assert_eq!(
+            resolve("inventory.txt"),
+            DatasetFormat::Csv(CsvFormat::default())
+        );
+        assert_eq!(
+            resolve("inventory"),
+            DatasetFormat::Csv(CsvFormat::default())
+        );
+        // most likely still a CSV export
+        assert_eq!(
+            resolve("inventory.export"),
+            DatasetFormat::Csv(CsvFormat::default())
+        );
+    }
+
+    #[test]
+    fn resolve_reads_the_csv_settings() {
+        assert_eq!(
+            DatasetFormat::resolve("inventory.csv", None, Some(";"), Some("CP1252")).unwrap(),
+            csv(b';', TextEncoding::Windows1252)
+        );
+        assert_eq!(
+            DatasetFormat::resolve("inventory.csv", Some("csv"), Some("tab"), Some("utf-8"))
+                .unwrap(),
+            csv(b'\t', TextEncoding::Utf8)
+        );
+        assert!(DatasetFormat::resolve("inventory.csv", None, Some(";;"), None).is_err());
+        assert!(DatasetFormat::resolve("inventory.csv", None, None, Some("utf-16")).is_err());
+    }
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(parse_delimiter(None).unwrap(), b',');
+        assert_eq!(parse_delimiter(Some("")).unwrap(), b',');
+        assert_eq!(parse_delimiter(Some("\\t")).unwrap(), b'\t');
+        assert_eq!(parse_delimiter(Some("tab")).unwrap(), b'\t');
+        assert_eq!(parse_delimiter(Some("|")).unwrap(), b'|');
+        let e = parse_delimiter(Some("||")).unwrap_err();
+        assert!(e.to_string().contains("single character, got ||"), "{}", e);
+    }
+
+    #[test]
+    fn encodings() {
+        for name in ["utf8", "UTF-8", " utf-8 ", ""] {
+            assert_eq!(parse_encoding(Some(name)).unwrap(), TextEncoding::Utf8);
+        }
+        assert_eq!(parse_encoding(None).unwrap(), TextEncoding::Utf8);
+        for name in ["windows-1252", "cp1252", "Latin1", "ISO-8859-1"] {
+            assert_eq!(
+                parse_encoding(Some(name)).unwrap(),
+                TextEncoding::Windows1252
+            );
+        }
+        let e = parse_encoding(Some("ebcdic")).unwrap_err();
+        assert!(
+            e.to_string().contains("Unsupported CSV encoding ebcdic"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn windows_1252_maps_the_high_punctuation() {
+        assert_eq!(
+            decode_windows_1252(b"\x93Caf\xe9\x94 \x80 5 \x96 \x85"),
+            "“Café” € 5 – …"
+        );
+        // bytes unassigned in Windows-1252 keep their Latin-1 control character
+        assert_eq!(decode_windows_1252(b"\x81\x9d"), "\u{81}\u{9d}");
+        assert_eq!(decode_windows_1252(b"plain"), "plain");
+    }
+
+    #[test]
+    fn utf8_byte_order_mark_is_stripped() {
+        assert_eq!(strip_utf8_bom(b"\xEF\xBB\xBFProject".to_vec()), b"Project");
+        assert_eq!(strip_utf8_bom(b"Project".to_vec()), b"Project");
+        assert_eq!(strip_utf8_bom(b"\xEF\xBB".to_vec()), b"\xEF\xBB");
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error, info};
+use polars::frame::DataFrame;
+
+use crate::data_format::DatasetFormat;
+use crate::data_source::{load_frame, source_from_uri, BlobDefaults};
+use crate::data_state::{
+    AppState, ClusterLoadInfo, DatasetConfig, DatasetLoadInfo, Datasets, ReloadStatus,
+};
+use crate::dataset_schema::{validate_consumer, validate_inventory};
+use crate::entities::APIError;
+use crate::kafka_cluster::{
+    cluster_admin_from_config, merge_with_cluster, ClusterAdmin, ClusterSnapshot,
+};
+use crate::lineage::LineageGraph;
+use crate::text_index::TextIndex;
+
+/// Read the dataset locations from the environment:
+///
+/// * `KAFKA_INVENTORY_FILE` / `KAFKA_CONSUMER_FILE` - file:// paths, http(s):// URLs,
+///   az://account/container/blob or bare blob names in `STORAGE_ACCOUNT` / `STORAGE_CONTAINER`
+/// * `KAFKA_CLUSTER` - optional live cluster to harvest topics and consumer groups from:
+///   bootstrap servers, or file://snapshot.json for a saved snapshot
+/// * `KAFKA_CLUSTER_PROPERTIES` - JSON object of extra librdkafka settings (security.protocol, sasl.*, ...)
+pub fn dataset_config_from_env() -> DatasetConfig {
+    let kafka_inventory_file =
+        std::env::var("KAFKA_INVENTORY_FILE").expect("KAFKA_INVENTORY_FILE must be set");
+    let kafka_consumer_file =
+        std::env::var("KAFKA_CONSUMER_FILE").expect("KAFKA_CONSUMER must be set");
+    let blob_defaults = BlobDefaults::from_env();
+    debug!("Azure Blob Storage defaults: {:?}", blob_defaults);
+
+    let kafka_cluster = std::env::var("KAFKA_CLUSTER").ok();
+    let kafka_cluster_properties = std::env::var("KAFKA_CLUSTER_PROPERTIES")
+        .ok()
+        .map(|p| {
+            serde_json::from_str::<HashMap<String, String>>(&p)
+                .expect("KAFKA_CLUSTER_PROPERTIES must be a JSON object of strings")
+                .into_iter()
+                .collect::<Vec<(String, String)>>()
+        })
+        .unwrap_or_default();
+
+    DatasetConfig {
+        kafka_inventory_source: source_from_uri(&kafka_inventory_file, &blob_defaults, true)
+            .expect("Invalid KAFKA_INVENTORY_FILE"),
+        kafka_inventory_format: DatasetFormat::from_env("KAFKA_INVENTORY", &kafka_inventory_file)
+            .expect("Invalid KAFKA_INVENTORY_FORMAT"),
+        kafka_consumer_source: source_from_uri(&kafka_consumer_file, &blob_defaults, true)
+            .expect("Invalid KAFKA_CONSUMER_FILE"),
+        kafka_consumer_format: DatasetFormat::from_env("KAFKA_CONSUMER", &kafka_consumer_file)
+            .expect("Invalid KAFKA_CONSUMER_FORMAT"),
+        kafka_cluster: kafka_cluster.map(|location| {
+            Arc::from(
+                cluster_admin_from_config(&location, &kafka_cluster_properties)
+                    .expect("Invalid KAFKA_CLUSTER"),
+            )
+        }),
+    }
+}
+
+/// Fetch and validate the kafka inventory and consumer datasets, merging in live cluster
+/// metadata when a cluster is configured. A failed harvest keeps the declared data and is
+/// only recorded in the returned status.
+pub async fn load_datasets(config: &DatasetConfig) -> Result<(Datasets, ReloadStatus), APIError> {
+    let (ds_inventory, inventory_source) = load_frame(
+        config.kafka_inventory_source.as_ref(),
+        &config.kafka_inventory_format,
+    )
+    .await?;
+    let ds_inventory = validate_inventory(ds_inventory)?;
+
+    let (ds_consumer, consumer_source) = load_frame(
+        config.kafka_consumer_source.as_ref(),
+        &config.kafka_consumer_format,
+    )
+    .await?;
+    let ds_consumer = validate_consumer(ds_consumer)?;
+
+    let now = chrono::Utc::now();
+    let mut status = ReloadStatus {
+        loaded_at: Some(now),
+        last_attempt_at: Some(now),
+        success: true,
+        kafka_inventory: Some(DatasetLoadInfo {
+            rows: ds_inventory.height(),
+            location: config.kafka_inventory_source.describe(),
+            source: inventory_source,
+        }),
+        kafka_consumer: Some(DatasetLoadInfo {
+            rows: ds_consumer.height(),
+            location: config.kafka_consumer_source.describe(),
+            source: consumer_source,
+        }),
+        kafka_cluster: None,
+        error: None,
+    };
+    let mut datasets = Datasets {
+        kafka_inventory: Some(ds_inventory),
+        kafka_consumer: Some(ds_consumer),
+        cluster_snapshot: None,
+        text_index: Arc::default(),
+        lineage: Arc::default(),
+    };
+
+    if let Some(cluster) = &config.kafka_cluster {
+        let mut cluster_info = ClusterLoadInfo {
+            location: cluster.describe(),
+            topics: 0,
+            consumer_groups: 0,
+            harvested_at: None,
+            error: None,
+        };
+        match harvest_cluster(cluster.as_ref(), &datasets).await {
+            Ok((ds_inventory, ds_consumer, snapshot)) => {
+                cluster_info.topics = snapshot.topics.len();
+                cluster_info.consumer_groups = snapshot.consumer_groups.len();
+                cluster_info.harvested_at = Some(snapshot.harvested_at);
+                datasets.kafka_inventory = Some(ds_inventory);
+                datasets.kafka_consumer = Some(ds_consumer);
+                datasets.cluster_snapshot = Some(snapshot);
+            }
+            Err(e) => {
+                error!(
+                    "Failed to harvest kafka cluster {}, using declared datasets only: {}",
+                    cluster.describe(),
+                    e
+                );
+                cluster_info.error = Some(e.to_string());
+            }
+        }
+        status.kafka_cluster = Some(cluster_info);
+    }
+    if let (Some(ds_inventory), Some(ds_consumer)) =
+        (&datasets.kafka_inventory, &datasets.kafka_consumer)
+    {
+        datasets.text_index = Arc::new(TextIndex::build(ds_inventory, ds_consumer)?);
+        datasets.lineage = Arc::new(LineageGraph::build(ds_inventory, ds_consumer)?);
+    }
+    Ok((datasets, status))
+}
+
+async fn harvest_cluster(
+    cluster: &dyn ClusterAdmin,
+    declared: &Datasets,
+) -> Result<(DataFrame, DataFrame, ClusterSnapshot), APIError> {
+    let snapshot = cluster.snapshot().await?;
+    debug!(
+        "Harvested {} topics and {} consumer groups from {}",
+        snapshot.topics.len(),
+        snapshot.consumer_groups.len(),
+        cluster.describe()
+    );
+    let (ds_inventory, ds_consumer) = match (&declared.kafka_inventory, &declared.kafka_consumer)
+    {
+        (Some(ds_inventory), Some(ds_consumer)) => {
+            merge_with_cluster(ds_inventory, ds_consumer, &snapshot)?
+        }
+        _ => return Err(APIError::new("No declared datasets to merge cluster data into")),
+    };
+    Ok((ds_inventory, ds_consumer, snapshot))
+}
+
+/// Reload the datasets and swap them into `app_state`.
+/// If loading or validation fails the current datasets are kept and the error is recorded.
+pub async fn reload(app_state: &AppState) -> ReloadStatus {
+    let now = chrono::Utc::now();
+    let result = load_datasets(&app_state.dataset_config).await;
+
+    let mut status = app_state.reload_status.write().unwrap();
+    match result {
+        Ok((datasets, new_status)) => {
+            info!(
+                "Reloaded datasets: {} inventory rows, {} consumer rows",
+                datasets.kafka_inventory.as_ref().map_or(0, |ds| ds.height()),
+                datasets.kafka_consumer.as_ref().map_or(0, |ds| ds.height())
+            );
+            *app_state.datasets.write().unwrap() = Arc::new(datasets);
+            *status = new_status;
+        }
+        Err(e) => {
+            error!("Failed to reload datasets, keeping current data: {}", e);
+            status.last_attempt_at = Some(now);
+            status.success = false;
+            status.error = Some(e.to_string());
+        }
+    }
+    status.clone()
+}
+
+/// Periodically reload the datasets on the current actix runtime.
+pub fn spawn_refresher(app_state: Arc<AppState>, every: Duration) {
+    info!("Reloading datasets every {} seconds", every.as_secs());
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(every);
+        // the first tick completes immediately, and the data was just loaded at startup
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            debug!("Scheduled dataset reload");
+            reload(&app_state).await;
+        }
+    });
+}
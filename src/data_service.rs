@@ -1,68 +1,42 @@
-use std::io::Cursor;
+use std::collections::{BTreeMap, BTreeSet};
 
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use log::{debug, error};
 use polars::lazy::prelude::*;
 use polars::prelude::*;
 
-use crate::entities::{APIError, SearchKafkaRequest, SearchKafkaResponse};
+use crate::dataset_schema::string_values;
+use crate::entities::{
+    APIError, AppDetail, AppDetailCounts, ErrorCode, FacetCount, MQTopicDescription, MatchMode,
+    SearchKafkaRequest, SearchKafkaResponse, SortOrder, TopicConsumer, TopicDetail,
+};
+use crate::query_language::{compile_query, query_columns};
+use crate::text_index::TextIndex;
 
 // Inventory file
 pub const COL_APP_OWNER_INVENTORY_FILE: &str = "Project";
 pub const COL_TOPIC_NAME_INVENTORY_FILE: &str = "Topic_Name_Kafka";
-
-const IDX_COL_APP_OWNER_INVENTORY_FILE: usize = 0;
-const IDX_COL_TOPIC_NAME_INVENTORY_FILE: usize = 1;
+// optional, indexed for search_all_text when present
+pub const COL_DESCRIPTION_INVENTORY_FILE: &str = "Description";
 
 // Consumer file
 pub const COL_CONSUMER_APP_NAME_CONSUMER_FILE: &str = "Project";
 pub const COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE: &str = "Consume_Topic";
 pub const COL_CONSUMER_GROUP_NAME_CONSUMER_FILE: &str = "Consumer_Group_Name";
 pub const COL_CONSUMER_APP_NAME_2_CONSUMER_FILE: &str = "Consumer_App";
-const IDX_COL_CONSUMER_APP_NAME_CONSUMER_FILE: usize = 0;
-
-pub fn read_csv(file: &String) -> PolarsResult<DataFrame> {
-    // Prefer `from_path` over `new` as it is faster.
-    CsvReadOptions::default()
-        .with_has_header(true)
-        .with_infer_schema_length(None)
-        .try_into_reader_with_file_path(Some(file.into()))?
-        .finish()
-}
-pub fn read_csv_from_string(data: &String) -> PolarsResult<DataFrame> {
-    // Prefer `from_path` over `new` as it is faster.
-    // Create a cursor from the CSV string
-    let cursor = Cursor::new(data);
-    let option = CsvReadOptions::default()
-        .with_has_header(true)
-        .with_infer_schema_length(None);
-
-    // Read the CSV string
-    CsvReader::new(cursor).finish()
-}
-pub fn post_login(ds: &DataFrame, user_name: &String, password: &String) -> Result<bool, APIError> {
-    debug!("Post login");
-    let ds = ds.clone();
-    let user_id = col("User_ID").eq(lit(user_name.as_str()));
-    let password = col("Password").eq(lit(password.as_str()));
 
-    let ds = ds
-        .lazy()
-        .filter(user_id.and(password))
-        .collect()
-        .map_err(|e| {
-            let message = format!(
-                "Failed to filter by username and password : [{:?}]",
-                e.to_string()
-            );
-            APIError::new(message.as_str())
-        })?;
-    Ok(ds.height() > 0)
-}
 pub fn get_app_list(ds: &DataFrame) -> Result<Vec<String>, APIError> {
     let mut app_list: Vec<String> = Vec::new();
     let ds = ds.clone();
+    // topics only found in the cluster have no owner
     let ds = ds
         .lazy()
+        .filter(
+            col(COL_APP_OWNER_INVENTORY_FILE)
+                .is_not_null()
+                .and(col(COL_APP_OWNER_INVENTORY_FILE).neq(lit(""))),
+        )
         .group_by([col(COL_APP_OWNER_INVENTORY_FILE)])
         .agg([col(COL_TOPIC_NAME_INVENTORY_FILE).count().alias("Count")])
         .sort(
@@ -73,15 +47,15 @@ pub fn get_app_list(ds: &DataFrame) -> Result<Vec<String>, APIError> {
         debug!("Failed to group by app owner: {}", e);
         APIError::new("Failed to group by app owner")
     })?;
-    map_single_result(&mut app_list, ds, IDX_COL_APP_OWNER_INVENTORY_FILE);
+    map_single_result(&mut app_list, ds, COL_APP_OWNER_INVENTORY_FILE)?;
     Ok(app_list)
 }
-pub fn get_topic_list(ds: &DataFrame, app_name: &String) -> Result<Vec<String>, APIError> {
+pub fn get_topic_list(ds: &DataFrame, app_name: &str) -> Result<Vec<String>, APIError> {
     let mut topic_list: Vec<String> = Vec::new();
     let ds = ds.clone();
     let ds = ds
         .lazy()
-        .filter(col(COL_APP_OWNER_INVENTORY_FILE).eq(lit(app_name.as_str())))
+        .filter(col(COL_APP_OWNER_INVENTORY_FILE).eq(lit(app_name)))
         .sort(
             [COL_TOPIC_NAME_INVENTORY_FILE],
             SortMultipleOptions::new().with_order_descending(false),
@@ -90,7 +64,7 @@ pub fn get_topic_list(ds: &DataFrame, app_name: &String) -> Result<Vec<String>,
         debug!("Failed to filter by topic name: {}", e);
         APIError::new("Failed to group by topic name")
     })?;
-    map_single_result(&mut topic_list, ds, IDX_COL_TOPIC_NAME_INVENTORY_FILE);
+    map_single_result(&mut topic_list, ds, COL_TOPIC_NAME_INVENTORY_FILE)?;
     Ok(topic_list)
 }
 
@@ -113,25 +87,225 @@ pub fn get_consumer_list(ds: &DataFrame) -> Result<Vec<String>, APIError> {
         APIError::new("Failed to group by consumer group")
     })?;
 
-    map_single_result(
-        &mut consumer_list,
-        ds,
-        IDX_COL_CONSUMER_APP_NAME_CONSUMER_FILE,
-    );
+    map_single_result(&mut consumer_list, ds, COL_CONSUMER_APP_NAME_CONSUMER_FILE)?;
     Ok(consumer_list)
 }
 
-fn map_single_result(topic_list: &mut Vec<String>, ds: DataFrame, idx: usize) {
+fn map_single_result(
+    topic_list: &mut Vec<String>,
+    ds: DataFrame,
+    column: &str,
+) -> Result<(), APIError> {
     debug!("Mapping result : {} ", ds);
-    for row in 0..ds.height() {
-        let row = ds.get(row).unwrap();
-        for (i, col) in row.iter().enumerate() {
-            if i == idx {
-                topic_list.push(col.to_string().replace("\"", ""));
+    for value in string_values(&ds, column)? {
+        topic_list.push(value.unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// The inventory row of a topic, its consumers and the MQ topics of its owner.
+pub fn get_topic_detail(
+    ds_inventory: &DataFrame,
+    ds_consumer: &DataFrame,
+    mq_topics: &[MQTopicDescription],
+    topic_name: &str,
+) -> Result<TopicDetail, APIError> {
+    let inventory_rows = collect_plan(
+        ds_inventory
+            .clone()
+            .lazy()
+            .filter(col(COL_TOPIC_NAME_INVENTORY_FILE).eq(lit(topic_name))),
+        "filter the inventory by topic name",
+    )?;
+    let consumer_rows = collect_plan(
+        ds_consumer
+            .clone()
+            .lazy()
+            .filter(col(COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE).eq(lit(topic_name))),
+        "filter the consumers by topic name",
+    )?;
+    if inventory_rows.height() == 0 && consumer_rows.height() == 0 {
+        return Err(APIError::with_code(
+            ErrorCode::NotFound,
+            &format!("No topic {}", topic_name),
+        ));
+    }
+
+    // the first inventory row wins if a topic is listed twice
+    let mut app_owner = None;
+    let mut description = None;
+    let mut attributes = BTreeMap::new();
+    for column in inventory_rows.get_column_names() {
+        let value = string_values(&inventory_rows, column)?
+            .into_iter()
+            .next()
+            .flatten();
+        match column {
+            COL_TOPIC_NAME_INVENTORY_FILE => {}
+            COL_APP_OWNER_INVENTORY_FILE => app_owner = value,
+            COL_DESCRIPTION_INVENTORY_FILE => description = value,
+            _ => {
+                attributes.insert(column.to_string(), value);
             }
         }
     }
+
+    // the consumer app is the project of the consumer row, as in search and app details
+    let consumers = string_values(&consumer_rows, COL_CONSUMER_APP_NAME_CONSUMER_FILE)?
+        .into_iter()
+        .zip(string_values(
+            &consumer_rows,
+            COL_CONSUMER_GROUP_NAME_CONSUMER_FILE,
+        )?)
+        .map(|(app, group)| TopicConsumer {
+            consumer_app: app.unwrap_or_default(),
+            consumer_group_id: group.unwrap_or_default(),
+        })
+        .collect();
+
+    // MQ topics of the same name, or published by the owner of the topic
+    let related_mq_topics = mq_topics
+        .iter()
+        .filter(|mq| {
+            mq.topic_name.eq_ignore_ascii_case(topic_name)
+                || app_owner.as_deref().is_some_and(|owner| {
+                    mq.publisher.eq_ignore_ascii_case(owner)
+                        || mq.business_module.eq_ignore_ascii_case(owner)
+                })
+        })
+        .cloned()
+        .collect();
+
+    Ok(TopicDetail {
+        topic_name: topic_name.to_string(),
+        app_owner,
+        description,
+        consumers,
+        consumer_group_ids: distinct_values(&consumer_rows, COL_CONSUMER_GROUP_NAME_CONSUMER_FILE)?,
+        consumer_apps: distinct_values(&consumer_rows, COL_CONSUMER_APP_NAME_CONSUMER_FILE)?,
+        related_mq_topics,
+        attributes,
+    })
 }
+
+/// The topics an application produces and consumes, and the applications on either side.
+pub fn get_app_detail(
+    ds_inventory: &DataFrame,
+    ds_consumer: &DataFrame,
+    app_name: &str,
+) -> Result<AppDetail, APIError> {
+    let produced = ds_inventory
+        .clone()
+        .lazy()
+        .filter(col(COL_APP_OWNER_INVENTORY_FILE).eq(lit(app_name)));
+    let consumed = ds_consumer
+        .clone()
+        .lazy()
+        .filter(col(COL_CONSUMER_APP_NAME_CONSUMER_FILE).eq(lit(app_name)));
+
+    // owners of the consumed topics
+    let upstream = ds_inventory.clone().lazy().join(
+        consumed
+            .clone()
+            .select([col(COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE)]),
+        [col(COL_TOPIC_NAME_INVENTORY_FILE)],
+        [col(COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE)],
+        JoinArgs::new(JoinType::Inner),
+    );
+    // consumers of the produced topics
+    let downstream = ds_consumer.clone().lazy().join(
+        produced
+            .clone()
+            .select([col(COL_TOPIC_NAME_INVENTORY_FILE)]),
+        [col(COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE)],
+        [col(COL_TOPIC_NAME_INVENTORY_FILE)],
+        JoinArgs::new(JoinType::Inner),
+    );
+
+    let produced = collect_plan(produced, "filter the inventory by app owner")?;
+    let consumed = collect_plan(consumed, "filter the consumers by app")?;
+    if produced.height() == 0 && consumed.height() == 0 {
+        return Err(APIError::with_code(
+            ErrorCode::NotFound,
+            &format!("No app {}", app_name),
+        ));
+    }
+    let upstream = collect_plan(upstream, "join the consumed topics with their owners")?;
+    let downstream = collect_plan(downstream, "join the produced topics with their consumers")?;
+
+    let produced_topics = distinct_values(&produced, COL_TOPIC_NAME_INVENTORY_FILE)?;
+    let consumed_topics = distinct_values(&consumed, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE)?;
+    let consumer_group_ids = distinct_values(&consumed, COL_CONSUMER_GROUP_NAME_CONSUMER_FILE)?;
+    // an app reading its own topics doesn't depend on itself
+    let mut upstream_apps = distinct_values(&upstream, COL_APP_OWNER_INVENTORY_FILE)?;
+    upstream_apps.retain(|app| app != app_name);
+    let mut downstream_apps = distinct_values(&downstream, COL_CONSUMER_APP_NAME_CONSUMER_FILE)?;
+    downstream_apps.retain(|app| app != app_name);
+
+    Ok(AppDetail {
+        app_name: app_name.to_string(),
+        counts: AppDetailCounts {
+            produced_topics: produced_topics.len(),
+            consumed_topics: consumed_topics.len(),
+            consumer_group_ids: consumer_group_ids.len(),
+            upstream_apps: upstream_apps.len(),
+            downstream_apps: downstream_apps.len(),
+        },
+        produced_topics,
+        consumed_topics,
+        consumer_group_ids,
+        upstream_apps,
+        downstream_apps,
+    })
+}
+
+fn collect_plan(plan: LazyFrame, action: &str) -> Result<DataFrame, APIError> {
+    plan.collect().map_err(|e| {
+        debug!("Failed to {}: {}", action, e);
+        APIError::new(&format!("Failed to {}", action))
+    })
+}
+
+// Sorted non-empty values of a column, without duplicates
+fn distinct_values(ds: &DataFrame, column: &str) -> Result<Vec<String>, APIError> {
+    let values: BTreeSet<String> = string_values(ds, column)?
+        .into_iter()
+        .flatten()
+        .filter(|v| !v.is_empty())
+        .collect();
+    Ok(values.into_iter().collect())
+}
+
+// Result fields of a search and the joined column each one is read from
+const SEARCH_RESULT_COLUMNS: [(&str, &str); 4] = [
+    ("app_owner", COL_APP_OWNER_INVENTORY_FILE),
+    ("topic_name", COL_TOPIC_NAME_INVENTORY_FILE),
+    ("consumer_group_id", COL_CONSUMER_GROUP_NAME_CONSUMER_FILE),
+    ("consumer_app", COL_CONSUMER_APP_NAME_2_CONSUMER_FILE),
+];
+// built from the other fields
+const SEARCH_RESULT_DESCRIPTION: &str = "description";
+pub const SEARCH_MAX_LIMIT: usize = 1000;
+const FACET_DEFAULT_LIMIT: usize = 20;
+// weight of a search_all_text match in each field
+const TEXT_FIELD_WEIGHTS: [(&str, f64); 5] = [
+    (COL_TOPIC_NAME_INVENTORY_FILE, 1.0),
+    (COL_APP_OWNER_INVENTORY_FILE, 0.8),
+    (COL_CONSUMER_APP_NAME_2_CONSUMER_FILE, 0.8),
+    (COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, 0.6),
+    (COL_DESCRIPTION_INVENTORY_FILE, 0.5),
+];
+
+/// One page of search results, projected to the requested fields.
+pub struct SearchPage {
+    pub rows: Vec<serde_json::Value>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub next_cursor: Option<String>,
+    pub facets: Option<BTreeMap<String, Vec<FacetCount>>>,
+}
+
 /**
  * Searches the inventory and consumer dataframes based on the provided search request.
  *
@@ -143,103 +317,414 @@ fn map_single_result(topic_list: &mut Vec<String>, ds: DataFrame, idx: usize) {
 pub fn search(
     ds_inventory: &DataFrame,
     ds_consumer: &DataFrame,
+    text_index: &TextIndex,
     search_request: &SearchKafkaRequest,
 ) -> Result<Vec<SearchKafkaResponse>, APIError> {
-    let mut result: Vec<SearchKafkaResponse> = Vec::new();
-
-    let mut expr = col(COL_CONSUMER_APP_NAME_2_CONSUMER_FILE).is_not_null();
+    let joined = search_plan(ds_inventory, ds_consumer, text_index, search_request)?
+        .select(result_columns())
+        .collect()
+        .map_err(|e| {
+            error!("Failed to filter dataframes: {}", e);
+            APIError::new("Failed to filter dataframes")
+        })?;
+    to_search_responses(&joined)
+}
 
-    if let Some(app_owner) = &search_request.app_owner {
-        expr = expr.and(col(COL_APP_OWNER_INVENTORY_FILE).eq(lit(app_owner.as_str())));
+/// Like `search`, but sorted and cut to the requested page inside the lazy plan, so only
+/// the rows of the page are materialized.
+pub fn search_page(
+    ds_inventory: &DataFrame,
+    ds_consumer: &DataFrame,
+    text_index: &TextIndex,
+    search_request: &SearchKafkaRequest,
+) -> Result<SearchPage, APIError> {
+    let offset = match (&search_request.cursor, search_request.offset) {
+        (Some(_), Some(_)) => {
+            return Err(APIError::with_code(
+                ErrorCode::InvalidRequest,
+                "Use either offset or cursor, not both",
+            ))
+        }
+        (Some(cursor), None) => decode_cursor(cursor)?,
+        (None, offset) => offset.unwrap_or(0),
+    };
+    let limit = search_request.limit;
+    if limit.is_some_and(|limit| limit == 0 || limit > SEARCH_MAX_LIMIT) {
+        return Err(APIError::with_code(
+            ErrorCode::InvalidRequest,
+            &format!("limit must be between 1 and {}", SEARCH_MAX_LIMIT),
+        ));
+    }
+    if let Some(fields) = &search_request.fields {
+        for field in fields {
+            if field != SEARCH_RESULT_DESCRIPTION
+                && !SEARCH_RESULT_COLUMNS.iter().any(|(name, _)| name == field)
+            {
+                return Err(APIError::with_code(
+                    ErrorCode::InvalidRequest,
+                    &format!(
+                        "Unknown field {}, expected one of {}, {}",
+                        field,
+                        result_field_names(),
+                        SEARCH_RESULT_DESCRIPTION
+                    ),
+                ));
+            }
+        }
     }
-    if let Some(topic_name) = &search_request.topic_name {
-        expr = expr.and(col(COL_TOPIC_NAME_INVENTORY_FILE).eq(lit(topic_name.as_str())));
+
+    let facet_limit = search_request.facet_limit.unwrap_or(FACET_DEFAULT_LIMIT);
+    if facet_limit == 0 || facet_limit > SEARCH_MAX_LIMIT {
+        return Err(APIError::with_code(
+            ErrorCode::InvalidRequest,
+            &format!("facet_limit must be between 1 and {}", SEARCH_MAX_LIMIT),
+        ));
     }
-    if let Some(consumer_app) = &search_request.consumer_app {
-        expr = expr.and(col(COL_CONSUMER_APP_NAME_2_CONSUMER_FILE).eq(lit(consumer_app.as_str())));
+    let mut facet_columns = Vec::new();
+    for facet in search_request.facets.iter().flatten() {
+        let column = result_column(facet).ok_or_else(|| {
+            APIError::with_code(
+                ErrorCode::InvalidRequest,
+                &format!(
+                    "Unknown facet {}, expected one of {}",
+                    facet,
+                    result_field_names()
+                ),
+            )
+        })?;
+        facet_columns.push((facet.clone(), column));
     }
 
-    // Rename column
-    let ds_consumer = ds_consumer
+    let mut plan = search_plan(ds_inventory, ds_consumer, text_index, search_request)?;
+    let total = plan
         .clone()
-        .lazy()
-        .with_column(
-            col(COL_CONSUMER_APP_NAME_CONSUMER_FILE).alias(COL_CONSUMER_APP_NAME_2_CONSUMER_FILE),
-        )
+        .select([col(COL_TOPIC_NAME_INVENTORY_FILE)])
         .collect()
         .map_err(|e| {
-            debug!("Failed to rename column: {}", e);
-            APIError::new("Failed to rename column")
+            error!("Failed to count search results: {}", e);
+            APIError::new("Failed to count search results")
+        })?
+        .height();
+    if offset > total {
+        return Err(APIError::with_code(
+            ErrorCode::InvalidRequest,
+            &format!("offset {} is past the {} results", offset, total),
+        ));
+    }
+    let facets = match &search_request.facets {
+        Some(_) => {
+            let mut facets = BTreeMap::new();
+            for (facet, column) in facet_columns {
+                facets.insert(facet, facet_counts(plan.clone(), column, facet_limit)?);
+            }
+            Some(facets)
+        }
+        None => None,
+    };
+
+    if let Some(sort_by) = &search_request.sort_by {
+        let sort_column = result_column(sort_by).ok_or_else(|| {
+            APIError::with_code(
+                ErrorCode::InvalidRequest,
+                &format!(
+                    "Can't sort by {}, expected one of {}",
+                    sort_by,
+                    result_field_names()
+                ),
+            )
         })?;
+        // the other result columns break ties, so pages don't overlap
+        let mut sort_columns = vec![sort_column];
+        sort_columns.extend(
+            SEARCH_RESULT_COLUMNS
+                .iter()
+                .map(|(_, column)| *column)
+                .filter(|column| *column != sort_column),
+        );
+        let descending = search_request.sort_order.unwrap_or_default() == SortOrder::Desc;
+        plan = plan.sort(
+            sort_columns,
+            SortMultipleOptions::new()
+                .with_order_descending(descending)
+                .with_nulls_last(true)
+                .with_maintain_order(true),
+        );
+    }
+    if offset > 0 || limit.is_some() {
+        plan = plan.slice(offset as i64, limit.unwrap_or(total) as IdxSize);
+    }
+    let rows = plan.select(result_columns()).collect().map_err(|e| {
+        error!("Failed to filter dataframes: {}", e);
+        APIError::new("Failed to filter dataframes")
+    })?;
 
-    debug!("Renamed column: {}", ds_consumer);
+    let rows = to_search_responses(&rows)?
+        .iter()
+        .map(|row| project_fields(row, search_request.fields.as_deref()))
+        .collect();
+    let next_cursor = limit
+        .map(|limit| offset.saturating_add(limit))
+        .filter(|next| *next < total)
+        .map(encode_cursor);
+    Ok(SearchPage {
+        rows,
+        total,
+        offset,
+        limit,
+        next_cursor,
+        facets,
+    })
+}
 
-    // join dataframes between inventory and consumer
-    let joined = ds_inventory
-        .join(
-            &ds_consumer,
-            [COL_TOPIC_NAME_INVENTORY_FILE],
-            [COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE],
-            JoinArgs::new(JoinType::Left),
+// Most frequent values of `column` among the search results, nulls are not counted.
+fn facet_counts(plan: LazyFrame, column: &str, limit: usize) -> Result<Vec<FacetCount>, APIError> {
+    let ds = plan
+        .filter(col(column).is_not_null())
+        .group_by([col(column)])
+        .agg([col(column).count().alias("Count")])
+        .sort(
+            ["Count", column],
+            SortMultipleOptions::new().with_order_descending_multi([true, false]),
         )
+        .limit(limit as IdxSize)
+        .collect()
+        .map_err(|e| {
+            error!("Failed to count facet {}: {}", column, e);
+            APIError::new("Failed to count facets")
+        })?;
+    let values = string_values(&ds, column)?;
+    let counts = ds
+        .column("Count")
+        .and_then(|c| c.cast(&DataType::UInt64))
+        .and_then(|c| c.u64().cloned())
         .map_err(|e| {
-            error!("Failed to join dataframes: {}", e);
-            APIError::new("Failed to join dataframes")
+            error!("Failed to read facet {}: {}", column, e);
+            APIError::new("Failed to count facets")
         })?;
+    Ok(values
+        .into_iter()
+        .zip(&counts)
+        .map(|(value, count)| FacetCount {
+            value: value.unwrap_or_default(),
+            count: count.unwrap_or_default() as usize,
+        })
+        .collect())
+}
 
-    let mut joined = joined.lazy().filter(expr).collect().map_err(|e| {
-        error!("Failed to filter dataframes: {}", e);
-        APIError::new("Failed to filter dataframes")
+// Join the inventory with its consumers and apply the filters of the request, lazily.
+// search_all_text ranks the rows instead, which needs them collected.
+fn search_plan(
+    ds_inventory: &DataFrame,
+    ds_consumer: &DataFrame,
+    text_index: &TextIndex,
+    search_request: &SearchKafkaRequest,
+) -> Result<LazyFrame, APIError> {
+    // Rename column
+    let ds_consumer = ds_consumer.clone().lazy().with_column(
+        col(COL_CONSUMER_APP_NAME_CONSUMER_FILE).alias(COL_CONSUMER_APP_NAME_2_CONSUMER_FILE),
+    );
+
+    // join dataframes between inventory and consumer
+    let joined = ds_inventory.clone().lazy().join(
+        ds_consumer,
+        [col(COL_TOPIC_NAME_INVENTORY_FILE)],
+        [col(COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE)],
+        JoinArgs::new(JoinType::Left),
+    );
+
+    let schema = joined.clone().schema().map_err(|e| {
+        error!("Failed to join dataframes: {}", e);
+        APIError::new("Failed to join dataframes")
     })?;
 
-    // Apply the filter to each column and combine the results
-    if let Some(filter_condition) = &search_request.search_all_text {
-        debug!("Filtering by search all text: {}", filter_condition);
-        let mut filter_expr = col(COL_CONSUMER_APP_NAME_CONSUMER_FILE)
-            .str()
-            .to_lowercase()
-            .str()
-            .contains(lit(filter_condition.as_str().to_lowercase()), false);
-        filter_expr = filter_expr.or(col(COL_TOPIC_NAME_INVENTORY_FILE)
-            .str()
-            .to_lowercase()
-            .str()
-            .contains(lit(filter_condition.as_str().to_lowercase()), false));
-        filter_expr = filter_expr.or(col(COL_CONSUMER_GROUP_NAME_CONSUMER_FILE)
-            .str()
-            .to_lowercase()
-            .str()
-            .contains(lit(filter_condition.as_str().to_lowercase()), false));
-        filter_expr = filter_expr.or(col(COL_CONSUMER_APP_NAME_2_CONSUMER_FILE)
-            .str()
-            .to_lowercase()
-            .str()
-            .contains(lit(filter_condition.as_str().to_string()), false));
-
-        joined = joined.lazy().filter(filter_expr).collect().map_err(|e| {
-            error!("Failed to filter dataframes: {}", e);
+    // Only topics that have a consumer are returned, unless the query is about Consumer_App
+    // itself, as in `Consumer_App is null` for the topics nobody consumes.
+    let consumed = col(COL_CONSUMER_APP_NAME_2_CONSUMER_FILE).is_not_null();
+    let mut expr = match &search_request.query {
+        Some(query) => {
+            debug!("Filtering by query: {}", query);
+            let query_expr = compile_query(query, &schema)?;
+            if query_columns(query, &schema)?
+                .iter()
+                .any(|c| c == COL_CONSUMER_APP_NAME_2_CONSUMER_FILE)
+            {
+                query_expr
+            } else {
+                consumed.and(query_expr)
+            }
+        }
+        None => consumed,
+    };
 
+    let name_filters = [
+        (
+            "app_owner",
+            COL_APP_OWNER_INVENTORY_FILE,
+            &search_request.app_owner,
+            search_request.app_owner_match,
+        ),
+        (
+            "topic_name",
+            COL_TOPIC_NAME_INVENTORY_FILE,
+            &search_request.topic_name,
+            search_request.topic_name_match,
+        ),
+        (
+            "consumer_app",
+            COL_CONSUMER_APP_NAME_2_CONSUMER_FILE,
+            &search_request.consumer_app,
+            search_request.consumer_app_match,
+        ),
+    ];
+    for (field, column, value, mode) in name_filters {
+        if let Some(value) = value {
+            expr = expr.and(match_expr(field, column, value, mode.unwrap_or_default())?);
+        }
+    }
+
+    let joined = joined.filter(expr);
+    match &search_request.search_all_text {
+        Some(text) => rank_by_text(joined, &schema, text_index, text),
+        None => Ok(joined),
+    }
+}
+
+// Keep the rows matching every word of `text`, best match first.
+fn rank_by_text(
+    joined: LazyFrame,
+    schema: &Schema,
+    text_index: &TextIndex,
+    text: &str,
+) -> Result<LazyFrame, APIError> {
+    debug!("Ranking by search all text: {}", text);
+    let query = text_index.query(text);
+    if query.is_empty() {
+        return Ok(joined);
+    }
+    let weights: Vec<(&str, f64)> = TEXT_FIELD_WEIGHTS
+        .iter()
+        .filter(|(column, _)| schema.contains(column))
+        .cloned()
+        .collect();
+    let rows = joined
+        .select(
+            weights
+                .iter()
+                .map(|(column, _)| col(column))
+                .collect::<Vec<Expr>>(),
+        )
+        .collect()
+        .map_err(|e| {
+            error!("Failed to filter dataframes: {}", e);
             APIError::new("Failed to filter dataframes")
         })?;
+    let values = weights
+        .iter()
+        .map(|(column, _)| string_values(&rows, column))
+        .collect::<Result<Vec<Vec<Option<String>>>, APIError>>()?;
 
-        debug!("Filtered by search all text: {}", joined);
+    let mut ranked: Vec<(IdxSize, f64)> = (0..rows.height())
+        .filter_map(|row| {
+            let fields: Vec<(Option<&str>, f64)> = values
+                .iter()
+                .zip(&weights)
+                .map(|(column, (_, weight))| (column[row].as_deref(), *weight))
+                .collect();
+            query.score(&fields).map(|score| (row as IdxSize, score))
+        })
+        .collect();
+    // stable, equally good matches keep the inventory order
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    debug!("{} of {} rows match {}", ranked.len(), rows.height(), text);
+
+    let indices = IdxCa::from_vec("Row", ranked.iter().map(|(row, _)| *row).collect());
+    let ranked_rows = rows.take(&indices).map_err(|e| {
+        error!("Failed to rank search results: {}", e);
+        APIError::new("Failed to rank search results")
+    })?;
+    Ok(ranked_rows.lazy())
+}
+
+// Filter of one name field of the request in the given match mode.
+fn match_expr(field: &str, column: &str, value: &str, mode: MatchMode) -> Result<Expr, APIError> {
+    let pattern = match mode {
+        MatchMode::Exact => return Ok(col(column).eq(lit(value))),
+        MatchMode::Prefix => return Ok(col(column).str().starts_with(lit(value))),
+        MatchMode::Glob => glob_to_regex(value),
+        MatchMode::Regex => value.to_string(),
+    };
+    // Validate here so a bad pattern is reported to the client instead of failing
+    // later inside the polars plan.
+    regex::Regex::new(&pattern).map_err(|e| {
+        APIError::with_code(
+            ErrorCode::InvalidRequest,
+            &format!(
+                "Invalid {} pattern for {}: {}",
+                format!("{:?}", mode).to_lowercase(),
+                field,
+                e
+            ),
+        )
+        .with_details(Some(serde_json::json!({
+            "field": field,
+            "pattern": value,
+            "error": e.to_string(),
+        })))
+    })?;
+    Ok(col(column).str().contains(lit(pattern), false))
+}
+
+// `payments.*.v2` -> `^payments\..*\.v2$`
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
     }
-    /////
-    // map result
+    pattern.push('$');
+    pattern
+}
+
+fn result_column(field: &str) -> Option<&'static str> {
+    SEARCH_RESULT_COLUMNS
+        .iter()
+        .find(|(name, _)| *name == field)
+        .map(|(_, column)| *column)
+}
+
+fn result_columns() -> Vec<Expr> {
+    SEARCH_RESULT_COLUMNS
+        .iter()
+        .map(|(_, column)| col(column))
+        .collect()
+}
+
+fn result_field_names() -> String {
+    SEARCH_RESULT_COLUMNS
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+fn to_search_responses(joined: &DataFrame) -> Result<Vec<SearchKafkaResponse>, APIError> {
+    let mut result: Vec<SearchKafkaResponse> = Vec::new();
+    // map result by column name, topics without a consumer come out of the left join as nulls
+    let app_owners = string_values(joined, COL_APP_OWNER_INVENTORY_FILE)?;
+    let topic_names = string_values(joined, COL_TOPIC_NAME_INVENTORY_FILE)?;
+    let consumer_groups = string_values(joined, COL_CONSUMER_GROUP_NAME_CONSUMER_FILE)?;
+    let consumer_apps = string_values(joined, COL_CONSUMER_APP_NAME_2_CONSUMER_FILE)?;
     for row in 0..joined.height() {
-        let mut search_kafka_response = SearchKafkaResponse::default();
-        let row = joined.get(row).unwrap();
-        for (i, col) in row.iter().enumerate() {
-            if i == 0 {
-                search_kafka_response.app_owner = col.to_string().replace("\"", "");
-            } else if i == 1 {
-                search_kafka_response.topic_name = col.to_string().replace("\"", "");
-            } else if i == 3 {
-                search_kafka_response.consumer_group_id = col.to_string().replace("\"", "");
-            } else if i == 5 {
-                search_kafka_response.consumer_app = col.to_string().replace("\"", "");
-            }
-        }
+        let mut search_kafka_response = SearchKafkaResponse {
+            app_owner: app_owners[row].clone().unwrap_or_default(),
+            topic_name: topic_names[row].clone().unwrap_or_default(),
+            consumer_group_id: consumer_groups[row].clone().unwrap_or_default(),
+            consumer_app: consumer_apps[row].clone().unwrap_or_default(),
+            ..Default::default()
+        };
 
         let mut description = String::new();
         description.push_str("App Owner or Producer is ");
@@ -256,3 +741,171 @@ pub fn search(
 
     Ok(result)
 }
+
+fn project_fields(row: &SearchKafkaResponse, fields: Option<&[String]>) -> serde_json::Value {
+    let mut value = serde_json::to_value(row).unwrap_or_default();
+    if let (Some(fields), serde_json::Value::Object(map)) = (fields, &mut value) {
+        map.retain(|name, _| fields.contains(name));
+    }
+    value
+}
+
+// Cursors are opaque to clients, today they only carry the offset of the next page
+fn encode_cursor(offset: usize) -> String {
+    URL_SAFE_NO_PAD.encode(format!("offset:{}", offset))
+}
+
+fn decode_cursor(cursor: &str) -> Result<usize, APIError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|text| text.strip_prefix("offset:")?.parse().ok())
+        .ok_or_else(|| APIError::with_code(ErrorCode::InvalidRequest, "Invalid cursor"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_format::read_csv_bytes;
+
+    fn fixture(name: &str) -> DataFrame {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        read_csv_bytes(std::fs::read(path).unwrap(), b',').unwrap()
+    }
+
+    fn request(json: serde_json::Value) -> SearchKafkaRequest {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn search_topics(search_request: &SearchKafkaRequest) -> Vec<String> {
+        let ds_inventory = fixture("kafka_inventory.csv");
+        let ds_consumer = fixture("kafka_consumer.csv");
+        let text_index = TextIndex::build(&ds_inventory, &ds_consumer).unwrap();
+        search(&ds_inventory, &ds_consumer, &text_index, search_request)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.topic_name)
+            .collect()
+    }
+
+    #[test]
+    fn search_returns_consumed_topics_unless_the_query_asks_about_consumers() {
+        assert_eq!(
+            search_topics(&request(serde_json::json!({}))),
+            ["payments.events"]
+        );
+        assert!(search_topics(&request(serde_json::json!({"query": "Project=orders"}))).is_empty());
+        assert_eq!(
+            search_topics(&request(
+                serde_json::json!({"query": "Consumer_App is null"})
+            )),
+            ["orders.created"]
+        );
+        assert_eq!(
+            search_topics(&request(
+                serde_json::json!({"query": "consumer_app is null or Project=payments"})
+            )),
+            ["payments.events", "orders.created"]
+        );
+    }
+
+    // five consumed topics, t1 to t5
+    fn page(search_request: serde_json::Value) -> Result<SearchPage, APIError> {
+        let topics = ["t3", "t1", "t5", "t2", "t4"];
+        let ds_inventory = df!(
+            COL_APP_OWNER_INVENTORY_FILE => ["payments"; 5],
+            COL_TOPIC_NAME_INVENTORY_FILE => topics,
+        )
+        .unwrap();
+        let ds_consumer = df!(
+            COL_CONSUMER_APP_NAME_CONSUMER_FILE => ["ledger"; 5],
+            COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE => topics,
+            COL_CONSUMER_GROUP_NAME_CONSUMER_FILE => ["ledger"; 5],
+        )
+        .unwrap();
+        let text_index = TextIndex::build(&ds_inventory, &ds_consumer).unwrap();
+        search_page(
+            &ds_inventory,
+            &ds_consumer,
+            &text_index,
+            &request(search_request),
+        )
+    }
+
+    fn page_topics(page: &SearchPage) -> Vec<&str> {
+        page.rows
+            .iter()
+            .map(|row| row["topic_name"].as_str().unwrap())
+            .collect()
+    }
+
+    fn invalid_request(search_request: serde_json::Value) -> String {
+        let e = page(search_request).err().unwrap();
+        assert_eq!(e.code(), ErrorCode::InvalidRequest);
+        e.to_string()
+    }
+
+    #[test]
+    fn paging_parameters_are_validated() {
+        assert!(invalid_request(serde_json::json!({"limit": 0})).contains("limit must be"));
+        assert!(
+            invalid_request(serde_json::json!({"limit": SEARCH_MAX_LIMIT + 1}))
+                .contains("limit must be")
+        );
+        assert!(
+            invalid_request(serde_json::json!({"offset": 1, "cursor": encode_cursor(1)}))
+                .contains("either offset or cursor")
+        );
+        assert!(
+            invalid_request(serde_json::json!({"cursor": "not a cursor"}))
+                .contains("Invalid cursor")
+        );
+        assert!(
+            invalid_request(serde_json::json!({"cursor": encode_cursor(6)}))
+                .contains("offset 6 is past the 5 results")
+        );
+        assert!(
+            invalid_request(serde_json::json!({"offset": 6, "limit": 2}))
+                .contains("past the 5 results")
+        );
+        // no overflow of offset + limit, and no wrapping to a negative slice offset
+        assert!(
+            invalid_request(serde_json::json!({"offset": usize::MAX, "limit": 2}))
+                .contains("past the 5 results")
+        );
+        assert!(
+            invalid_request(serde_json::json!({"offset": i64::MAX as usize + 1}))
+                .contains("past the 5 results")
+        );
+
+        let end = page(serde_json::json!({"offset": 5, "limit": 2})).unwrap();
+        assert!(end.rows.is_empty());
+        assert_eq!(end.total, 5);
+        assert_eq!(end.next_cursor, None);
+    }
+
+    #[test]
+    fn cursors_page_through_all_results() {
+        let mut topics = Vec::new();
+        let mut search_request = serde_json::json!({"limit": 2, "sort_by": "topic_name"});
+        loop {
+            let page = page(search_request.clone()).unwrap();
+            assert_eq!(page.total, 5);
+            topics.extend(page_topics(&page).iter().map(|t| t.to_string()));
+            match page.next_cursor {
+                Some(cursor) => search_request["cursor"] = cursor.into(),
+                None => break,
+            }
+        }
+        assert_eq!(topics, ["t1", "t2", "t3", "t4", "t5"]);
+
+        let page = page(serde_json::json!({"offset": 3, "sort_by": "topic_name"})).unwrap();
+        assert_eq!(page_topics(&page), ["t4", "t5"]);
+        assert_eq!(page.next_cursor, None);
+
+        for offset in [0, 2, usize::MAX] {
+            assert_eq!(decode_cursor(&encode_cursor(offset)).unwrap(), offset);
+        }
+    }
+}
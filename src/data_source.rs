@@ -0,0 +1,447 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use log::{debug, error};
+use polars::frame::DataFrame;
+use serde::{Deserialize, Serialize};
+
+use crate::data_format::{read_frame, DatasetFormat};
+use crate::data_utils::fetch_blob_az;
+use crate::entities::APIError;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DatasetOrigin {
+    #[serde(rename = "file")]
+    File,
+    #[serde(rename = "http")]
+    Http,
+    #[serde(rename = "blob")]
+    Blob,
+    #[serde(rename = "local_fallback")]
+    LocalFallback,
+}
+
+pub struct FetchedDataset {
+    pub bytes: Vec<u8>,
+    pub origin: DatasetOrigin,
+}
+
+/// Somewhere a dataset file can be read from.
+pub trait DatasetSource: Send + Sync {
+    /// URI of the dataset, for logs and status output
+    fn describe(&self) -> String;
+    fn fetch(&self) -> LocalBoxFuture<'_, Result<FetchedDataset, APIError>>;
+}
+
+pub struct LocalFileSource {
+    path: PathBuf,
+}
+
+impl LocalFileSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LocalFileSource { path: path.into() }
+    }
+}
+
+impl DatasetSource for LocalFileSource {
+    fn describe(&self) -> String {
+        format!("file://{}", self.path.display())
+    }
+
+    fn fetch(&self) -> LocalBoxFuture<'_, Result<FetchedDataset, APIError>> {
+        async move {
+            let bytes = std::fs::read(&self.path).map_err(|e| {
+                APIError::new(&format!("Failed to read {}: {}", self.path.display(), e))
+            })?;
+            Ok(FetchedDataset {
+                bytes,
+                origin: DatasetOrigin::File,
+            })
+        }
+        .boxed_local()
+    }
+}
+
+pub struct HttpSource {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpSource {
+    pub fn new(url: &str) -> Self {
+        HttpSource {
+            url: url.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl DatasetSource for HttpSource {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    fn fetch(&self) -> LocalBoxFuture<'_, Result<FetchedDataset, APIError>> {
+        async move {
+            let response = self
+                .client
+                .get(&self.url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| APIError::new(&format!("Failed to fetch {}: {}", self.url, e)))?;
+            let bytes = response
+                .bytes()
+                .await
+                .map_err(|e| APIError::new(&format!("Failed to read {}: {}", self.url, e)))?;
+            Ok(FetchedDataset {
+                bytes: bytes.to_vec(),
+                origin: DatasetOrigin::Http,
+            })
+        }
+        .boxed_local()
+    }
+}
+
+pub struct AzureBlobSource {
+    account_name: String,
+    container_name: String,
+    blob_name: String,
+}
+
+impl AzureBlobSource {
+    pub fn new(account_name: &str, container_name: &str, blob_name: &str) -> Self {
+        AzureBlobSource {
+            account_name: account_name.to_string(),
+            container_name: container_name.to_string(),
+            blob_name: blob_name.to_string(),
+        }
+    }
+}
+
+impl DatasetSource for AzureBlobSource {
+    fn describe(&self) -> String {
+        format!(
+            "az://{}/{}/{}",
+            self.account_name, self.container_name, self.blob_name
+        )
+    }
+
+    fn fetch(&self) -> LocalBoxFuture<'_, Result<FetchedDataset, APIError>> {
+        async move {
+            let bytes =
+                fetch_blob_az(&self.account_name, &self.container_name, &self.blob_name).await?;
+            Ok(FetchedDataset {
+                bytes,
+                origin: DatasetOrigin::Blob,
+            })
+        }
+        .boxed_local()
+    }
+}
+
+/// Tries `primary` first and reads `fallback` if it fails, reporting the data as a local fallback.
+pub struct FallbackSource {
+    primary: Box<dyn DatasetSource>,
+    fallback: Box<dyn DatasetSource>,
+}
+
+impl DatasetSource for FallbackSource {
+    fn describe(&self) -> String {
+        format!(
+            "{} (fallback {})",
+            self.primary.describe(),
+            self.fallback.describe()
+        )
+    }
+
+    fn fetch(&self) -> LocalBoxFuture<'_, Result<FetchedDataset, APIError>> {
+        async move {
+            match self.primary.fetch().await {
+                Ok(dataset) => Ok(dataset),
+                Err(e) => {
+                    error!(
+                        "Failed to fetch {}: {}, reading {}",
+                        self.primary.describe(),
+                        e,
+                        self.fallback.describe()
+                    );
+                    let dataset = self.fallback.fetch().await?;
+                    Ok(FetchedDataset {
+                        bytes: dataset.bytes,
+                        origin: DatasetOrigin::LocalFallback,
+                    })
+                }
+            }
+        }
+        .boxed_local()
+    }
+}
+
+/// Default Azure Blob Storage location for dataset names given without a URI scheme.
+#[derive(Debug, Clone, Default)]
+pub struct BlobDefaults {
+    pub account_name: Option<String>,
+    pub container_name: Option<String>,
+}
+
+impl BlobDefaults {
+    pub fn from_env() -> Self {
+        BlobDefaults {
+            account_name: std::env::var("STORAGE_ACCOUNT").ok(),
+            container_name: std::env::var("STORAGE_CONTAINER").ok(),
+        }
+    }
+}
+
+/// Select a dataset source from its configured location:
+///
+/// * `file:///path/to/file.csv` - local file
+/// * `http://...` or `https://...` - plain HTTP(S) GET
+/// * `az://account/container/path/to/blob.csv` - Azure Blob Storage
+/// * a bare name - blob in the default storage account and container, read from
+///   the local file of the same name instead if `local_fallback` is set and the blob fails
+pub fn source_from_uri(
+    uri: &str,
+    defaults: &BlobDefaults,
+    local_fallback: bool,
+) -> Result<Arc<dyn DatasetSource>, APIError> {
+    debug!("Resolving dataset source: {}", uri);
+    if let Some(path) = uri.strip_prefix("file://") {
+        return Ok(Arc::new(LocalFileSource::new(path)));
+    }
+    if uri.starts_with("http://") || uri.starts_with("https://") {
+        return Ok(Arc::new(HttpSource::new(uri)));
+    }
+    if let Some(location) = uri.strip_prefix("az://") {
+        let mut parts = location.splitn(3, '/');
+        return match (parts.next(), parts.next(), parts.next()) {
+            (Some(account), Some(container), Some(blob))
+                if !account.is_empty() && !container.is_empty() && !blob.is_empty() =>
+            {
+                Ok(Arc::new(AzureBlobSource::new(account, container, blob)))
+            }
+            _ => Err(APIError::new(&format!(
+                "Invalid Azure Blob location {}, expected az://account/container/blob",
+                uri
+            ))),
+        };
+    }
+    if uri.contains("://") {
+        return Err(APIError::new(&format!(
+            "Unsupported dataset location {}",
+            uri
+        )));
+    }
+
+    let blob = match (&defaults.account_name, &defaults.container_name) {
+        (Some(account), Some(container)) => AzureBlobSource::new(account, container, uri),
+        _ if local_fallback => return Ok(Arc::new(LocalFileSource::new(uri))),
+        _ => {
+            return Err(APIError::new(&format!(
+                "Dataset {} has no URI scheme and STORAGE_ACCOUNT / STORAGE_CONTAINER are not set",
+                uri
+            )))
+        }
+    };
+    if local_fallback {
+        Ok(Arc::new(FallbackSource {
+            primary: Box::new(blob),
+            fallback: Box::new(LocalFileSource::new(uri)),
+        }))
+    } else {
+        Ok(Arc::new(blob))
+    }
+}
+
+/// Fetch a dataset from `source` and read it into a frame.
+pub async fn load_frame(
+    source: &dyn DatasetSource,
+    format: &DatasetFormat,
+) -> Result<(DataFrame, DatasetOrigin), APIError> {
+    let dataset = source.fetch().await?;
+    let ds = read_frame(dataset.bytes, format).map_err(|e| {
+        APIError::new(&format!(
+            "Failed to read {:?} dataset from {}: {}",
+            format,
+            source.describe(),
+            e
+        ))
+    })?;
+    Ok((ds, dataset.origin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_format::CsvFormat;
+    use crate::dataset_schema::validate_inventory;
+
+    const INVENTORY_FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/kafka_inventory.csv"
+    );
+    const MISSING_FIXTURE: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/missing.csv");
+
+    fn csv() -> DatasetFormat {
+        DatasetFormat::Csv(CsvFormat::default())
+    }
+
+    #[actix_web::test]
+    async fn file_source_reads_the_fixture() {
+        let source = LocalFileSource::new(INVENTORY_FIXTURE);
+        let (ds, origin) = load_frame(&source, &csv()).await.unwrap();
+        assert_eq!(origin, DatasetOrigin::File);
+        let ds = validate_inventory(ds).unwrap();
+        assert_eq!(ds.height(), 2);
+
+        let missing = LocalFileSource::new(MISSING_FIXTURE);
+        assert!(load_frame(&missing, &csv()).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn fallback_source_reads_the_fallback_when_the_primary_fails() {
+        let source = FallbackSource {
+            primary: Box::new(LocalFileSource::new(MISSING_FIXTURE)),
+            fallback: Box::new(LocalFileSource::new(INVENTORY_FIXTURE)),
+        };
+        let (ds, origin) = load_frame(&source, &csv()).await.unwrap();
+        assert_eq!(origin, DatasetOrigin::LocalFallback);
+        assert_eq!(ds.height(), 2);
+
+        let source = FallbackSource {
+            primary: Box::new(LocalFileSource::new(INVENTORY_FIXTURE)),
+            fallback: Box::new(LocalFileSource::new(MISSING_FIXTURE)),
+        };
+        let (_, origin) = load_frame(&source, &csv()).await.unwrap();
+        assert_eq!(origin, DatasetOrigin::File);
+
+        let source = FallbackSource {
+            primary: Box::new(LocalFileSource::new(MISSING_FIXTURE)),
+            fallback: Box::new(LocalFileSource::new(MISSING_FIXTURE)),
+        };
+        assert!(source.fetch().await.is_err());
+    }
+
+    #[test]
+    fn source_from_uri_dispatches_on_the_scheme() {
+        let none = BlobDefaults::default();
+        let defaults = BlobDefaults {
+            account_name: Some("acct".to_string()),
+            container_name: Some("data".to_string()),
+        };
+        let describe = |uri: &str, defaults: &BlobDefaults, local_fallback: bool| {
+            source_from_uri(uri, defaults, local_fallback).map(|s| s.describe())
+        };
+
+        assert_eq!(
+            describe("file:///data/inventory.csv", &none, false).unwrap(),
+            "file:///data/inventory.csv"
+        );
+        assert_eq!(
+            describe("https://example.com/inventory.csv", &none, false).unwrap(),
+            "https://example.com/inventory.csv"
+        );
+        assert_eq!(
+            describe("http://example.com/inventory.csv", &none, false).unwrap(),
+            "http://example.com/inventory.csv"
+        );
+        assert_eq!(
+            describe("az://acct/data/dir/inventory.csv", &none, false).unwrap(),
+            "az://acct/data/dir/inventory.csv"
+        );
+        assert!(describe("az://acct/data", &none, false).is_err());
+        assert!(describe("az://acct//inventory.csv", &none, false).is_err());
+        assert!(describe("ftp://example.com/inventory.csv", &none, false).is_err());
+
+        assert_eq!(
+            describe("inventory.csv", &defaults, false).unwrap(),
+            "az://acct/data/inventory.csv"
+        );
+        assert_eq!(
+            describe("inventory.csv", &defaults, true).unwrap(),
+            "az://acct/data/inventory.csv (fallback file://inventory.csv)"
+        );
+        assert_eq!(
+            describe("inventory.csv", &none, true).unwrap(),
+            "file://inventory.csv"
+        );
+        assert!(describe("inventory.csv", &none, false).is_err());
+    }
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    // Read a fixture in the format its name and the CSV settings select
+    async fn load_fixture(
+        name: &str,
+        delimiter: Option<&str>,
+        encoding: Option<&str>,
+    ) -> Result<DataFrame, APIError> {
+        let path = fixture(name);
+        let format = DatasetFormat::resolve(&path, None, delimiter, encoding)?;
+        let (ds, _) = load_frame(&LocalFileSource::new(path), &format).await?;
+        validate_inventory(ds)
+    }
+
+    fn column(ds: &DataFrame, name: &str) -> Vec<Option<String>> {
+        crate::dataset_schema::string_values(ds, name).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn json_array_and_ndjson_read_the_same_rows_as_csv() {
+        let expected = load_fixture("kafka_inventory.csv", None, None)
+            .await
+            .unwrap();
+        for name in ["kafka_inventory.json", "kafka_inventory.ndjson"] {
+            let ds = load_fixture(name, None, None).await.unwrap();
+            for c in ["Project", "Topic_Name_Kafka", "Description"] {
+                assert_eq!(column(&ds, c), column(&expected, c), "{} {}", name, c);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn json_array_is_not_read_as_ndjson() {
+        let source = LocalFileSource::new(fixture("kafka_inventory.json"));
+        let e = load_frame(&source, &DatasetFormat::NdJson)
+            .await
+            .unwrap_err();
+        assert!(
+            e.to_string().contains("Failed to read NdJson dataset"),
+            "{}",
+            e
+        );
+    }
+
+    #[actix_web::test]
+    async fn excel_csv_export_is_decoded() {
+        let ds = load_fixture("kafka_inventory_excel.csv", Some(";"), Some("windows-1252"))
+            .await
+            .unwrap();
+        assert_eq!(
+            column(&ds, "Description"),
+            [
+                Some("Zahlungen für “Kasse”".to_string()),
+                Some("New orders – café".to_string())
+            ]
+        );
+        // read as UTF-8 with commas it is one unknown column
+        assert!(load_fixture("kafka_inventory_excel.csv", None, None)
+            .await
+            .is_err());
+    }
+
+    #[actix_web::test]
+    async fn byte_order_mark_is_not_part_of_the_first_column() {
+        let ds = load_fixture("kafka_inventory_bom.csv", None, None)
+            .await
+            .unwrap();
+        assert_eq!(ds.get_column_names()[0], "Project");
+        assert_eq!(ds.height(), 2);
+    }
+}
@@ -1,23 +1,123 @@
+use std::sync::{Arc, RwLock};
+
+use crate::api_keys::ApiKeyStore;
+use crate::auth_provider::AuthProvider;
+use crate::data_format::DatasetFormat;
+use crate::data_source::{DatasetOrigin, DatasetSource};
+use crate::entities::MQTopicDescription;
 use crate::entities_ai::AISearchIndex;
+use crate::health_report::NamingRules;
+use crate::kafka_cluster::{ClusterAdmin, ClusterSnapshot};
+use crate::lineage::LineageGraph;
+use crate::oidc::OidcProvider;
+use crate::signing_keys::KeyRing;
+use crate::text_index::TextIndex;
+use crate::token_store::TokenStore;
 use async_openai::config::AzureConfig;
+use chrono::{DateTime, Utc};
 use polars::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone)]
 pub struct AppState {
-    pub kafka_inventory: Option<DataFrame>,
-    pub kafka_consumer: Option<DataFrame>,
-    pub user_authentication: Option<DataFrame>,
-    pub jwt_secret: String,
+    // Kafka inventory and consumer, swapped as a whole on reload
+    pub datasets: Arc<RwLock<Arc<Datasets>>>,
+    pub dataset_config: DatasetConfig,
+    pub reload_status: Arc<RwLock<ReloadStatus>>,
+    // Password logins, None when only OIDC is configured
+    pub auth_provider: Option<Arc<dyn AuthProvider>>,
+    pub oidc: Option<Arc<OidcProvider>>,
+    // Refresh tokens and revoked access tokens
+    pub token_store: Arc<TokenStore>,
+    pub api_keys: Arc<ApiKeyStore>,
+    // Keys for signing and verifying the tokens issued by login
+    pub signing_keys: Arc<KeyRing>,
     // Azure AI Search
     pub azure_ai_search_url: Option<String>,
     pub azure_ai_search_key: Option<String>,
     pub azure_ai_search_indexes: Option<Vec<AISearchIndex>>,
+    // read from AI_SEARCH_WITH_SEMANTIC, ai_search always sends a semantic query for now
+    #[allow(dead_code)]
     pub azure_ai_search_use_semantics: bool,
     // Open AI
     //pub azure_open_ai_url: Option<String>,
     //pub azure_open_ai_key: Option<String>,
     // static knowledge
     pub knowledge: Option<String>,
+    // MQ Pub/Sub topics of the knowledge file, for the topic details
+    pub mq_topics: Vec<MQTopicDescription>,
+    // Naming conventions checked by the health report
+    pub naming_rules: NamingRules,
     // Azure Open AI Configuration
     pub open_ai_config: AzureConfig,
 }
+
+impl AppState {
+    /// Snapshot of the current datasets; a reload never changes a snapshot already taken.
+    pub fn datasets(&self) -> Arc<Datasets> {
+        self.datasets.read().unwrap().clone()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Datasets {
+    pub kafka_inventory: Option<DataFrame>,
+    pub kafka_consumer: Option<DataFrame>,
+    // Live cluster metadata merged into the frames above, if a cluster is configured
+    pub cluster_snapshot: Option<ClusterSnapshot>,
+    // Words of the frames above for search_all_text
+    pub text_index: Arc<TextIndex>,
+    // Producer -> topic -> consumer links of the frames above
+    pub lineage: Arc<LineageGraph>,
+}
+
+#[derive(Clone)]
+pub struct DatasetConfig {
+    pub kafka_inventory_source: Arc<dyn DatasetSource>,
+    pub kafka_inventory_format: DatasetFormat,
+    pub kafka_consumer_source: Arc<dyn DatasetSource>,
+    pub kafka_consumer_format: DatasetFormat,
+    pub kafka_cluster: Option<Arc<dyn ClusterAdmin>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DatasetLoadInfo {
+    #[serde(rename = "rows")]
+    pub rows: usize,
+    #[serde(rename = "location")]
+    pub location: String,
+    #[serde(rename = "source")]
+    pub source: DatasetOrigin,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClusterLoadInfo {
+    #[serde(rename = "location")]
+    pub location: String,
+    #[serde(rename = "topics")]
+    pub topics: usize,
+    #[serde(rename = "consumer_groups")]
+    pub consumer_groups: usize,
+    #[serde(rename = "harvested_at")]
+    pub harvested_at: Option<DateTime<Utc>>,
+    #[serde(rename = "error")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReloadStatus {
+    #[serde(rename = "loaded_at")]
+    pub loaded_at: Option<DateTime<Utc>>,
+    #[serde(rename = "last_attempt_at")]
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    #[serde(rename = "success")]
+    pub success: bool,
+    #[serde(rename = "kafka_inventory")]
+    pub kafka_inventory: Option<DatasetLoadInfo>,
+    #[serde(rename = "kafka_consumer")]
+    pub kafka_consumer: Option<DatasetLoadInfo>,
+    #[serde(rename = "kafka_cluster")]
+    pub kafka_cluster: Option<ClusterLoadInfo>,
+    #[serde(rename = "error")]
+    pub error: Option<String>,
+}
@@ -4,16 +4,14 @@ use azure_storage::StorageCredentials;
 use azure_storage_blobs::prelude::*;
 use futures::StreamExt;
 use log::debug;
-use polars::frame::DataFrame;
 
-use crate::data_service::read_csv_from_string;
 use crate::entities::APIError;
 
-pub async fn fetch_dataset_az_blob(
+pub async fn fetch_blob_az(
     account_name: &str,
     container_name: &str,
     blob_name: &str,
-) -> Result<DataFrame, APIError> {
+) -> Result<Vec<u8>, APIError> {
     debug!(
         "Fetching dataset from Azure Blob Storage: {}/{}/{}",
         account_name, container_name, blob_name
@@ -45,13 +43,11 @@ pub async fn fetch_dataset_az_blob(
     while let Some(value) = stream.next().await {
         match value {
             Ok(bytes) => {
-                // Process the chunk of bytes
-                // For example, you can write to a file or process the data
+                // Chunks are raw byte ranges of the blob, so they are appended as-is
                 let datas = bytes.data.collect().await;
                 match datas {
                     Ok(data) => {
-                        let data = String::from_utf8_lossy(&data);
-                        buffer.push(data.to_string());
+                        buffer.extend_from_slice(&data);
                     }
                     Err(e) => {
                         return Err(APIError::new(&format!("Error reading blob: {}", e)));
@@ -63,11 +59,6 @@ pub async fn fetch_dataset_az_blob(
             }
         }
     }
-    let csv_data = buffer.join("\n");
-    debug!("CSV data: {}", csv_data);
 
-    let dataset = read_csv_from_string(&csv_data)
-        .map_err(|e| APIError::new(&format!("Failed to read CSV from string: {}", e)))?;
-
-    Ok(dataset)
+    Ok(buffer)
 }
@@ -0,0 +1,128 @@
+use log::{debug, warn};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::data_service::{
+    COL_APP_OWNER_INVENTORY_FILE, COL_CONSUMER_APP_NAME_CONSUMER_FILE,
+    COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE,
+    COL_TOPIC_NAME_INVENTORY_FILE,
+};
+use crate::entities::APIError;
+
+pub struct DatasetSchema {
+    pub name: &'static str,
+    pub required_columns: &'static [&'static str],
+}
+
+pub const INVENTORY_SCHEMA: DatasetSchema = DatasetSchema {
+    name: "kafka inventory",
+    required_columns: &[COL_APP_OWNER_INVENTORY_FILE, COL_TOPIC_NAME_INVENTORY_FILE],
+};
+
+pub const CONSUMER_SCHEMA: DatasetSchema = DatasetSchema {
+    name: "kafka consumer",
+    required_columns: &[
+        COL_CONSUMER_APP_NAME_CONSUMER_FILE,
+        COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE,
+        COL_CONSUMER_GROUP_NAME_CONSUMER_FILE,
+    ],
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SchemaReport {
+    #[serde(rename = "dataset")]
+    pub dataset: String,
+    #[serde(rename = "missing_columns")]
+    pub missing_columns: Vec<String>,
+    #[serde(rename = "extra_columns")]
+    pub extra_columns: Vec<String>,
+    #[serde(rename = "found_columns")]
+    pub found_columns: Vec<String>,
+}
+
+impl DatasetSchema {
+    pub fn report(&self, ds: &DataFrame) -> SchemaReport {
+        let found_columns: Vec<String> = ds
+            .get_column_names()
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let missing_columns = self
+            .required_columns
+            .iter()
+            .filter(|c| !found_columns.iter().any(|f| f == *c))
+            .map(|c| c.to_string())
+            .collect();
+        let extra_columns = found_columns
+            .iter()
+            .filter(|f| !self.required_columns.contains(&f.as_str()))
+            .cloned()
+            .collect();
+        SchemaReport {
+            dataset: self.name.to_string(),
+            missing_columns,
+            extra_columns,
+            found_columns,
+        }
+    }
+
+    /// Check that every required column is present and cast them to strings, so the
+    /// rest of the service can read them by name without caring how the CSV was inferred.
+    /// Extra columns are kept (they are shown in detail views) but logged.
+    pub fn validate(&self, ds: DataFrame) -> Result<DataFrame, APIError> {
+        let report = self.report(&ds);
+        if !report.missing_columns.is_empty() {
+            let message = format!(
+                "Dataset {} is missing columns [{}], found [{}]",
+                report.dataset,
+                report.missing_columns.join(", "),
+                report.found_columns.join(", ")
+            );
+            return Err(APIError::new(&message).with_details(serde_json::to_value(&report).ok()));
+        }
+        if !report.extra_columns.is_empty() {
+            warn!(
+                "Dataset {} has extra columns [{}]",
+                report.dataset,
+                report.extra_columns.join(", ")
+            );
+        }
+
+        let casts: Vec<Expr> = self
+            .required_columns
+            .iter()
+            .map(|c| col(c).cast(DataType::String))
+            .collect();
+        let ds = ds.lazy().with_columns(casts).collect().map_err(|e| {
+            APIError::new(&format!(
+                "Dataset {} has columns that cannot be read as text: {}",
+                self.name, e
+            ))
+        })?;
+        debug!("Validated dataset {}: {}", self.name, ds);
+        Ok(ds)
+    }
+}
+
+pub fn validate_inventory(ds: DataFrame) -> Result<DataFrame, APIError> {
+    INVENTORY_SCHEMA.validate(ds)
+}
+
+pub fn validate_consumer(ds: DataFrame) -> Result<DataFrame, APIError> {
+    CONSUMER_SCHEMA.validate(ds)
+}
+
+/// Read a text column by name, mapping nulls (e.g. from a left join) to `None`.
+pub fn string_values(ds: &DataFrame, column: &str) -> Result<Vec<Option<String>>, APIError> {
+    let series = ds
+        .column(column)
+        .and_then(|c| c.cast(&DataType::String))
+        .map_err(|e| APIError::new(&format!("Failed to read column {}: {}", column, e)))?;
+    let values = series
+        .str()
+        .map_err(|e| APIError::new(&format!("Failed to read column {}: {}", column, e)))?
+        .into_iter()
+        .map(|v| v.map(|s| s.to_string()))
+        .collect();
+    Ok(values)
+}
@@ -0,0 +1,65 @@
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::data_service::{
+    COL_APP_OWNER_INVENTORY_FILE, COL_CONSUMER_APP_NAME_CONSUMER_FILE,
+    COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE,
+    COL_TOPIC_NAME_INVENTORY_FILE,
+};
+use crate::dataset_schema::string_values;
+use crate::entities::APIError;
+
+/// A topic declared in the kafka inventory, owned (produced) by `project`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Topic {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "project")]
+    pub project: String,
+}
+
+/// One row of the consumer dataset: `consumer_app` reads `topic_name` with `consumer_group`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ConsumerBinding {
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    #[serde(rename = "consumer_group")]
+    pub consumer_group: String,
+    #[serde(rename = "consumer_app")]
+    pub consumer_app: String,
+}
+
+pub fn topics_from_frame(ds: &DataFrame) -> Result<Vec<Topic>, APIError> {
+    let projects = string_values(ds, COL_APP_OWNER_INVENTORY_FILE)?;
+    let names = string_values(ds, COL_TOPIC_NAME_INVENTORY_FILE)?;
+
+    Ok(projects
+        .into_iter()
+        .zip(names)
+        .filter_map(|(project, name)| {
+            Some(Topic {
+                name: name?,
+                project: project.unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+pub fn consumer_bindings_from_frame(ds: &DataFrame) -> Result<Vec<ConsumerBinding>, APIError> {
+    let apps = string_values(ds, COL_CONSUMER_APP_NAME_CONSUMER_FILE)?;
+    let topics = string_values(ds, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE)?;
+    let groups = string_values(ds, COL_CONSUMER_GROUP_NAME_CONSUMER_FILE)?;
+
+    Ok(apps
+        .into_iter()
+        .zip(topics)
+        .zip(groups)
+        .filter_map(|((app, topic), group)| {
+            Some(ConsumerBinding {
+                topic_name: topic?,
+                consumer_group: group.unwrap_or_default(),
+                consumer_app: app.unwrap_or_default(),
+            })
+        })
+        .collect())
+}
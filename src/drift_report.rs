@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{consumer_bindings_from_frame, topics_from_frame};
+use crate::entities::APIError;
+use crate::kafka_cluster::{ClusterSnapshot, COL_RECORD_SOURCE, RECORD_SOURCE_INVENTORY};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MissingTopic {
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    #[serde(rename = "app_owner")]
+    pub app_owner: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UndeclaredConsumption {
+    #[serde(rename = "consumer_group_id")]
+    pub consumer_group_id: String,
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    // empty when the group isn't in the consumer dataset at all
+    #[serde(rename = "consumer_app")]
+    pub consumer_app: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InactiveGroup {
+    #[serde(rename = "consumer_group_id")]
+    pub consumer_group_id: String,
+    #[serde(rename = "consumer_app")]
+    pub consumer_app: String,
+    // cluster state of the group, or "missing" if the cluster doesn't know it
+    #[serde(rename = "state")]
+    pub state: String,
+}
+
+/// Differences between the declared inventory / consumer datasets and the live cluster.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DriftReport {
+    #[serde(rename = "harvested_at")]
+    pub harvested_at: DateTime<Utc>,
+    #[serde(rename = "declared_topics_missing")]
+    pub declared_topics_missing: Vec<MissingTopic>,
+    #[serde(rename = "undeclared_topics")]
+    pub undeclared_topics: Vec<String>,
+    #[serde(rename = "undeclared_consumption")]
+    pub undeclared_consumption: Vec<UndeclaredConsumption>,
+    #[serde(rename = "inactive_groups")]
+    pub inactive_groups: Vec<InactiveGroup>,
+}
+
+/// Only the rows read from the declared datasets, dropping rows merged in from the cluster.
+pub(crate) fn declared_rows(ds: &DataFrame) -> Result<DataFrame, APIError> {
+    if ds.column(COL_RECORD_SOURCE).is_err() {
+        return Ok(ds.clone());
+    }
+    ds.clone()
+        .lazy()
+        .filter(col(COL_RECORD_SOURCE).eq(lit(RECORD_SOURCE_INVENTORY)))
+        .collect()
+        .map_err(|e| APIError::new(&format!("Failed to filter declared rows: {}", e)))
+}
+
+pub fn drift_report(
+    ds_inventory: &DataFrame,
+    ds_consumer: &DataFrame,
+    snapshot: &ClusterSnapshot,
+) -> Result<DriftReport, APIError> {
+    let declared_topics = topics_from_frame(&declared_rows(ds_inventory)?)?;
+    let declared_bindings = consumer_bindings_from_frame(&declared_rows(ds_consumer)?)?;
+
+    let live_topics: BTreeSet<&str> = snapshot.topics.iter().map(|t| t.as_str()).collect();
+    let declared_topic_names: BTreeSet<&str> =
+        declared_topics.iter().map(|t| t.name.as_str()).collect();
+
+    let mut declared_topics_missing: Vec<MissingTopic> = declared_topics
+        .iter()
+        .filter(|t| !live_topics.contains(t.name.as_str()))
+        .map(|t| MissingTopic {
+            topic_name: t.name.clone(),
+            app_owner: t.project.clone(),
+        })
+        .collect();
+    declared_topics_missing.sort_by(|a, b| a.topic_name.cmp(&b.topic_name));
+    declared_topics_missing.dedup_by(|a, b| a.topic_name == b.topic_name);
+
+    let undeclared_topics = live_topics
+        .iter()
+        .filter(|t| !t.starts_with("__") && !declared_topic_names.contains(*t))
+        .map(|t| t.to_string())
+        .collect();
+
+    // group id -> (app, declared topics), bindings without a group can't be matched to the cluster
+    let mut declared_groups: BTreeMap<&str, (&str, BTreeSet<&str>)> = BTreeMap::new();
+    for binding in declared_bindings
+        .iter()
+        .filter(|b| !b.consumer_group.is_empty())
+    {
+        let entry = declared_groups
+            .entry(binding.consumer_group.as_str())
+            .or_insert((binding.consumer_app.as_str(), BTreeSet::new()));
+        entry.1.insert(binding.topic_name.as_str());
+    }
+
+    let mut undeclared_consumption = Vec::new();
+    for group in &snapshot.consumer_groups {
+        let declared = declared_groups.get(group.group_id.as_str());
+        for topic in &group.topics {
+            if declared.is_none_or(|(_, topics)| !topics.contains(topic.as_str())) {
+                undeclared_consumption.push(UndeclaredConsumption {
+                    consumer_group_id: group.group_id.clone(),
+                    topic_name: topic.clone(),
+                    consumer_app: declared.map(|(app, _)| app.to_string()).unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    let mut inactive_groups = Vec::new();
+    for (group_id, (app, _)) in &declared_groups {
+        let live = snapshot
+            .consumer_groups
+            .iter()
+            .find(|g| g.group_id == *group_id);
+        let state = match live {
+            None => "missing".to_string(),
+            Some(g) if g.topics.is_empty() => g.state.clone(),
+            Some(_) => continue,
+        };
+        inactive_groups.push(InactiveGroup {
+            consumer_group_id: group_id.to_string(),
+            consumer_app: app.to_string(),
+            state,
+        });
+    }
+
+    Ok(DriftReport {
+        harvested_at: snapshot.harvested_at,
+        declared_topics_missing,
+        undeclared_topics,
+        undeclared_consumption,
+        inactive_groups,
+    })
+}
+
+impl DriftReport {
+    /// Plain text rendering for the command line.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&format!(
+            "Drift report, cluster harvested at {}\n",
+            self.harvested_at
+        ));
+
+        text.push_str(&format!(
+            "\nDeclared topics missing from the cluster ({}):\n",
+            self.declared_topics_missing.len()
+        ));
+        for t in &self.declared_topics_missing {
+            text.push_str(&format!("  {} (owner {})\n", t.topic_name, t.app_owner));
+        }
+
+        text.push_str(&format!(
+            "\nTopics in the cluster but not in the inventory ({}):\n",
+            self.undeclared_topics.len()
+        ));
+        for t in &self.undeclared_topics {
+            text.push_str(&format!("  {}\n", t));
+        }
+
+        text.push_str(&format!(
+            "\nConsumer groups reading undeclared topics ({}):\n",
+            self.undeclared_consumption.len()
+        ));
+        for c in &self.undeclared_consumption {
+            let app = if c.consumer_app.is_empty() {
+                "undeclared group"
+            } else {
+                c.consumer_app.as_str()
+            };
+            text.push_str(&format!(
+                "  {} -> {} ({})\n",
+                c.consumer_group_id, c.topic_name, app
+            ));
+        }
+
+        text.push_str(&format!(
+            "\nDeclared consumer groups that are inactive ({}):\n",
+            self.inactive_groups.len()
+        ));
+        for g in &self.inactive_groups {
+            text.push_str(&format!(
+                "  {} of {} ({})\n",
+                g.consumer_group_id, g.consumer_app, g.state
+            ));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_service::{
+        COL_APP_OWNER_INVENTORY_FILE, COL_CONSUMER_APP_NAME_CONSUMER_FILE,
+        COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE,
+        COL_TOPIC_NAME_INVENTORY_FILE,
+    };
+    use crate::kafka_cluster::ConsumerGroupInfo;
+
+    fn group(group_id: &str, state: &str, topics: &[&str]) -> ConsumerGroupInfo {
+        ConsumerGroupInfo {
+            group_id: group_id.to_string(),
+            state: state.to_string(),
+            topics: topics.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    // payments.refund is declared only, audit.log is live only
+    fn report() -> DriftReport {
+        let inventory = df!(
+            COL_APP_OWNER_INVENTORY_FILE => ["payments", "payments"],
+            COL_TOPIC_NAME_INVENTORY_FILE => ["payments.txn", "payments.refund"],
+        )
+        .unwrap();
+        let consumer = df!(
+            COL_CONSUMER_APP_NAME_CONSUMER_FILE => ["ledger", "ledger", "fraud", "reports"],
+            COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE => [
+                "payments.txn",
+                "payments.refund",
+                "payments.txn",
+                "payments.txn",
+            ],
+            COL_CONSUMER_GROUP_NAME_CONSUMER_FILE => [
+                Some("ledger-payments"),
+                Some("ledger-payments"),
+                Some("fraud-txn"),
+                None,
+            ],
+        )
+        .unwrap();
+        let snapshot = ClusterSnapshot {
+            harvested_at: Utc::now(),
+            topics: vec![
+                "payments.txn".to_string(),
+                "audit.log".to_string(),
+                "__consumer_offsets".to_string(),
+            ],
+            consumer_groups: vec![
+                group("ledger-payments", "Stable", &["payments.txn", "audit.log"]),
+                group("audit-sink", "Stable", &["audit.log"]),
+                group("fraud-txn", "Empty", &[]),
+            ],
+        };
+        drift_report(&inventory, &consumer, &snapshot).unwrap()
+    }
+
+    #[test]
+    fn declared_topics_missing_from_the_cluster() {
+        let missing = report().declared_topics_missing;
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].topic_name, "payments.refund");
+        assert_eq!(missing[0].app_owner, "payments");
+    }
+
+    #[test]
+    fn cluster_topics_not_declared_skip_internal_topics() {
+        assert_eq!(report().undeclared_topics, ["audit.log"]);
+    }
+
+    #[test]
+    fn consumption_not_declared_for_the_group() {
+        let consumption: Vec<(String, String, String)> = report()
+            .undeclared_consumption
+            .into_iter()
+            .map(|c| (c.consumer_group_id, c.topic_name, c.consumer_app))
+            .collect();
+        assert_eq!(
+            consumption,
+            [
+                (
+                    "ledger-payments".to_string(),
+                    "audit.log".to_string(),
+                    "ledger".to_string()
+                ),
+                (
+                    "audit-sink".to_string(),
+                    "audit.log".to_string(),
+                    String::new()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_groups_without_live_topics_are_inactive() {
+        let inactive: Vec<(String, String, String)> = report()
+            .inactive_groups
+            .into_iter()
+            .map(|g| (g.consumer_group_id, g.consumer_app, g.state))
+            .collect();
+        // the reports binding has no group and is not reported
+        assert_eq!(
+            inactive,
+            [(
+                "fraud-txn".to_string(),
+                "fraud".to_string(),
+                "Empty".to_string()
+            )]
+        );
+
+        let inventory = df!(
+            COL_APP_OWNER_INVENTORY_FILE => ["payments"],
+            COL_TOPIC_NAME_INVENTORY_FILE => ["payments.txn"],
+        )
+        .unwrap();
+        let consumer = df!(
+            COL_CONSUMER_APP_NAME_CONSUMER_FILE => ["ledger"],
+            COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE => ["payments.txn"],
+            COL_CONSUMER_GROUP_NAME_CONSUMER_FILE => ["ledger-payments"],
+        )
+        .unwrap();
+        let snapshot = ClusterSnapshot {
+            harvested_at: Utc::now(),
+            topics: vec!["payments.txn".to_string()],
+            consumer_groups: Vec::new(),
+        };
+        let report = drift_report(&inventory, &consumer, &snapshot).unwrap();
+        assert_eq!(report.inactive_groups.len(), 1);
+        assert_eq!(report.inactive_groups[0].state, "missing");
+    }
+}
@@ -1,11 +1,16 @@
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display, Formatter};
 
 use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
-use actix_web::{error, HttpRequest, HttpResponse, Responder, ResponseError};
+use actix_web::{error, HttpRequest, HttpResponse, Responder};
 use log::error;
 use serde::{Deserialize, Serialize};
 
+use crate::api_keys::ApiKey;
+use crate::authorization::Role;
+use crate::token_store::random_token;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserLogin {
     #[serde(rename = "username")]
@@ -21,20 +26,74 @@ pub struct JwtResponse {
     pub token_type: String,
     #[serde(rename = "expires_in")]
     pub expires_in: usize,
+    #[serde(rename = "refresh_token", skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(rename = "refresh_expires_in", skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_in: Option<usize>,
 }
 
-#[derive(Debug, derive_more::Display)]
-pub enum AuthError {
-    #[display("Unauthorized")]
-    Unauthorized,
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RefreshTokenRequest {
+    #[serde(rename = "refresh_token")]
+    pub refresh_token: String,
 }
 
-impl ResponseError for AuthError {
-    fn error_response(&self) -> HttpResponse {
-        match self {
-            AuthError::Unauthorized => HttpResponse::Unauthorized().finish(),
-        }
-    }
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LogoutRequest {
+    #[serde(rename = "refresh_token")]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateApiKeyRequest {
+    #[serde(rename = "name")]
+    pub name: String,
+    // viewer, i.e. read-only, if not given
+    #[serde(rename = "role")]
+    pub role: Option<Role>,
+    // never expires if not given
+    #[serde(rename = "expires_in_days")]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreatedApiKey {
+    #[serde(rename = "api_key")]
+    pub api_key: ApiKey,
+    // only returned once, send it in the X-API-Key header
+    #[serde(rename = "secret")]
+    pub secret: String,
+}
+
+/// Revoke one access token by id, or every token of a user.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevokeTokensRequest {
+    #[serde(rename = "jti")]
+    pub jti: Option<String>,
+    #[serde(rename = "exp")]
+    pub exp: Option<usize>,
+    #[serde(rename = "user_id")]
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoginMethods {
+    #[serde(rename = "password")]
+    pub password: bool,
+    #[serde(rename = "oidc")]
+    pub oidc: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallback {
+    #[serde(rename = "code")]
+    pub code: Option<String>,
+    #[serde(rename = "state")]
+    pub state: Option<String>,
+    #[serde(rename = "error")]
+    pub error: Option<String>,
+    #[serde(rename = "error_description")]
+    pub error_description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -49,6 +108,12 @@ pub struct Claims {
     iss: String, // Issuer
     #[serde(rename = "aud")]
     aud: String, // Audience
+    #[serde(rename = "jti", default)]
+    jti: String, // Token ID, for revocation
+    #[serde(rename = "groups", default, skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<String>, // Groups from the identity provider
+    #[serde(rename = "roles", default, skip_serializing_if = "Vec::is_empty")]
+    roles: Vec<Role>,
 }
 
 impl Claims {
@@ -60,8 +125,61 @@ impl Claims {
             iat,
             iss,
             aud,
+            jti: random_token(24),
+            groups: Vec::new(),
+            roles: Vec::new(),
         }
     }
+
+    /// Issue time and id of a token issued elsewhere; `iat` stays now if the token has none.
+    pub fn with_issued(mut self, iat: Option<usize>, jti: String) -> Self {
+        if let Some(iat) = iat {
+            self.iat = iat;
+        }
+        self.jti = jti;
+        self
+    }
+
+    pub fn with_roles(mut self, roles: Vec<Role>) -> Self {
+        self.roles = roles;
+        self
+    }
+
+    /// True if any of the token's roles is `role` or above it.
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.iter().any(|r| *r >= role)
+    }
+
+    pub fn with_groups(mut self, groups: Vec<String>) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    pub fn sub(&self) -> &str {
+        &self.sub
+    }
+
+    pub fn exp(&self) -> usize {
+        self.exp
+    }
+
+    pub fn iat(&self) -> usize {
+        self.iat
+    }
+
+    pub fn jti(&self) -> &str {
+        &self.jti
+    }
+
+    #[cfg(test)]
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+
+    #[cfg(test)]
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -72,10 +190,107 @@ pub struct SearchKafkaRequest {
     pub topic_name: Option<String>,
     #[serde(rename = "consumer_app")]
     pub consumer_app: Option<String>,
+    // how app_owner, topic_name and consumer_app are matched, exact if not set
+    #[serde(rename = "app_owner_match")]
+    pub app_owner_match: Option<MatchMode>,
+    #[serde(rename = "topic_name_match")]
+    pub topic_name_match: Option<MatchMode>,
+    #[serde(rename = "consumer_app_match")]
+    pub consumer_app_match: Option<MatchMode>,
     #[serde(rename = "search_all_text")]
     pub search_all_text: Option<String>,
     #[serde(rename = "ai_search_query")]
     pub ai_search_query: Option<String>,
+    #[serde(rename = "query")]
+    pub query: Option<String>,
+    // paging of the search results, cursor is the next_cursor of the previous page
+    #[serde(rename = "limit")]
+    pub limit: Option<usize>,
+    #[serde(rename = "offset")]
+    pub offset: Option<usize>,
+    #[serde(rename = "cursor")]
+    pub cursor: Option<String>,
+    #[serde(rename = "sort_by")]
+    pub sort_by: Option<String>,
+    #[serde(rename = "sort_order")]
+    pub sort_order: Option<SortOrder>,
+    // result fields to return, all of them if not set
+    #[serde(rename = "fields")]
+    pub fields: Option<Vec<String>>,
+    // result fields to count the values of over all results, not just the page
+    #[serde(rename = "facets")]
+    pub facets: Option<Vec<String>>,
+    #[serde(rename = "facet_limit")]
+    pub facet_limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    #[default]
+    Exact,
+    Prefix,
+    // `*` matches any text and `?` one character, the whole value must match
+    Glob,
+    // matches anywhere in the value unless anchored
+    Regex,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LineageQuery {
+    #[serde(rename = "direction")]
+    pub direction: Option<LineageDirection>,
+    // app hops from the root app
+    #[serde(rename = "depth")]
+    pub depth: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineageDirection {
+    // the producers an app depends on
+    Upstream,
+    // the consumers depending on an app
+    #[default]
+    Downstream,
+    Both,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenderQuery {
+    #[serde(rename = "format")]
+    pub format: Option<ExportFormat>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Mermaid,
+    Dot,
+    PlantUml,
+    GraphMl,
+    Json,
+}
+
+/// A topic or an app about to change, exactly one of the two.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImpactRequest {
+    #[serde(rename = "topic_name")]
+    pub topic_name: Option<String>,
+    #[serde(rename = "app_name")]
+    pub app_name: Option<String>,
+    // app hops to follow, up to the lineage maximum if not set
+    #[serde(rename = "max_depth")]
+    pub max_depth: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -92,57 +307,83 @@ pub struct SearchKafkaResponse {
     pub description: String,
 }
 
-impl Into<FlowChartItem> for SearchKafkaResponse {
-    fn into(self) -> FlowChartItem {
-        FlowChartItem {
-            project_name_owner_alias: self.app_owner.clone().replace(" ", "_").to_lowercase(),
-            project_name_owner: self.app_owner.clone(),
-            kafka_topic: self.topic_name.clone(),
-            consumer_group: self.consumer_group_id.clone(),
-            project_name_consume: self.consumer_app.clone(),
-            project_name_consume_alias: self.consumer_app.clone().replace(" ", "_").to_lowercase(),
-        }
-    }
+/// A topic of the MQ Pub/Sub knowledge file.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MQTopicDescription {
+    #[serde(rename = "business_module")]
+    pub business_module: String,
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    #[serde(rename = "publisher")]
+    pub publisher: String,
+    #[serde(rename = "remark")]
+    pub remark: String,
 }
 
-impl From<FlowChartItem> for SearchKafkaResponse {
-    fn from(item: FlowChartItem) -> Self {
-        SearchKafkaResponse {
-            app_owner: item.project_name_owner.clone(),
-            topic_name: item.kafka_topic.clone(),
-            consumer_group_id: item.consumer_group.clone(),
-            consumer_app: item.project_name_consume.clone(),
-            description: "".to_string(),
-        }
-    }
+/// One kafka topic with its owner, its consumers and the MQ topics related to it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TopicDetail {
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    // None for topics known only from the consumers or the cluster
+    #[serde(rename = "app_owner", skip_serializing_if = "Option::is_none")]
+    pub app_owner: Option<String>,
+    #[serde(rename = "description", skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "consumers")]
+    pub consumers: Vec<TopicConsumer>,
+    #[serde(rename = "consumer_group_ids")]
+    pub consumer_group_ids: Vec<String>,
+    #[serde(rename = "consumer_apps")]
+    pub consumer_apps: Vec<String>,
+    #[serde(rename = "related_mq_topics")]
+    pub related_mq_topics: Vec<MQTopicDescription>,
+    // the other inventory columns, by column name
+    #[serde(rename = "attributes")]
+    pub attributes: BTreeMap<String, Option<String>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
-pub struct FlowChartItem {
-    project_name_owner_alias: String,
-    project_name_owner: String,
-    pub kafka_topic: String,
-    pub consumer_group: String,
-    project_name_consume: String,
-    project_name_consume_alias: String,
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TopicConsumer {
+    #[serde(rename = "consumer_app")]
+    pub consumer_app: String,
+    #[serde(rename = "consumer_group_id")]
+    pub consumer_group_id: String,
 }
-impl FlowChartItem {
-    pub(crate) fn to_print_string(&self) -> String {
-        format!(
-            "{}[{}] ---> {} ---> {} ---> {}[{}]",
-            self.project_name_owner_alias,
-            self.project_name_owner,
-            self.kafka_topic,
-            self.consumer_group,
-            self.project_name_consume_alias,
-            self.project_name_consume
-        )
-    }
+
+/// Everything an application produces and consumes, and the applications on either side.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AppDetail {
+    #[serde(rename = "app_name")]
+    pub app_name: String,
+    #[serde(rename = "produced_topics")]
+    pub produced_topics: Vec<String>,
+    #[serde(rename = "consumed_topics")]
+    pub consumed_topics: Vec<String>,
+    #[serde(rename = "consumer_group_ids")]
+    pub consumer_group_ids: Vec<String>,
+    // owners of the consumed topics
+    #[serde(rename = "upstream_apps")]
+    pub upstream_apps: Vec<String>,
+    // consumers of the produced topics
+    #[serde(rename = "downstream_apps")]
+    pub downstream_apps: Vec<String>,
+    #[serde(rename = "counts")]
+    pub counts: AppDetailCounts,
 }
-impl Display for FlowChartItem {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_print_string())
-    }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AppDetailCounts {
+    #[serde(rename = "produced_topics")]
+    pub produced_topics: usize,
+    #[serde(rename = "consumed_topics")]
+    pub consumed_topics: usize,
+    #[serde(rename = "consumer_group_ids")]
+    pub consumer_group_ids: usize,
+    #[serde(rename = "upstream_apps")]
+    pub upstream_apps: usize,
+    #[serde(rename = "downstream_apps")]
+    pub downstream_apps: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -151,33 +392,124 @@ pub struct APIResponse<T: Debug + Serialize + Clone> {
     pub(crate) data: T,
 }
 
+/// One page of a result list, `total` counts all results.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct APIPagedResponse<T: Debug + Serialize + Clone> {
+    #[serde(rename = "data")]
+    pub(crate) data: Vec<T>,
+    #[serde(rename = "total")]
+    pub(crate) total: usize,
+    #[serde(rename = "offset")]
+    pub(crate) offset: usize,
+    #[serde(rename = "limit", skip_serializing_if = "Option::is_none")]
+    pub(crate) limit: Option<usize>,
+    #[serde(rename = "next_cursor", skip_serializing_if = "Option::is_none")]
+    pub(crate) next_cursor: Option<String>,
+    // field -> most frequent values, for the searches that asked for facets
+    #[serde(rename = "facets", skip_serializing_if = "Option::is_none")]
+    pub(crate) facets: Option<BTreeMap<String, Vec<FacetCount>>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FacetCount {
+    #[serde(rename = "value")]
+    pub value: String,
+    #[serde(rename = "count")]
+    pub count: usize,
+}
+
+/// Machine readable error codes, each one is answered with a fixed HTTP status.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidRequest,
+    InvalidQuery,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    AccountLocked,
+    TooManyRequests,
+    // the kafka datasets or cluster data needed for the request are not loaded
+    DataUnavailable,
+    // identity provider or blob storage
+    UpstreamUnavailable,
+    UpstreamAiUnavailable,
+    UpstreamAiTimeout,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest | ErrorCode::InvalidQuery => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::AccountLocked => StatusCode::LOCKED,
+            ErrorCode::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::DataUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::UpstreamUnavailable | ErrorCode::UpstreamAiUnavailable => {
+                StatusCode::BAD_GATEWAY
+            }
+            ErrorCode::UpstreamAiTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct APIError {
-    #[serde(rename = "error")]
-    error: String,
+    #[serde(rename = "code")]
+    code: ErrorCode,
+    #[serde(rename = "message")]
+    message: String,
+    // set by RequestIdMiddleware when the error is sent
+    #[serde(rename = "request_id", skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(rename = "details", skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
 }
 
 impl APIError {
-    pub fn new(error: &str) -> APIError {
+    /// An internal error, use `with_code` for anything the client can act on.
+    pub fn new(message: &str) -> APIError {
+        APIError::with_code(ErrorCode::Internal, message)
+    }
+    pub fn with_code(code: ErrorCode, message: &str) -> APIError {
         APIError {
-            error: error.to_string(),
+            code,
+            message: message.to_string(),
+            request_id: None,
+            details: None,
         }
     }
+    pub fn with_details(mut self, details: Option<serde_json::Value>) -> APIError {
+        self.details = details;
+        self
+    }
+    pub fn with_request_id(mut self, request_id: &str) -> APIError {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
 }
 impl Display for APIError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.error)
+        write!(f, "{}", self.message)
     }
 }
 
 impl error::ResponseError for APIError {
     fn status_code(&self) -> StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        self.code.status_code()
     }
     fn error_response(&self) -> HttpResponse {
+        let body = serde_json::to_string(self).unwrap_or_else(|_| self.to_string());
         HttpResponse::build(self.status_code())
             .insert_header(ContentType::json())
-            .body(self.to_string())
+            .body(body)
     }
 }
 
@@ -185,18 +517,30 @@ impl<T: Debug + Serialize + Clone> Responder for APIResponse<T> {
     type Body = actix_web::body::BoxBody;
 
     fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
-        // Serialize the struct to a JSON string
-        let body = match serde_json::to_string(&self) {
-            Ok(json) => json,
-            Err(e) => {
-                error!("Failed to serialize response: {}", e);
-                return HttpResponse::InternalServerError().finish();
-            }
-        };
+        json_response(&self)
+    }
+}
 
-        // Create an HTTP response with JSON content type
-        HttpResponse::Ok()
-            .content_type(ContentType::json())
-            .body(body)
+impl<T: Debug + Serialize + Clone> Responder for APIPagedResponse<T> {
+    type Body = actix_web::body::BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        json_response(&self)
     }
 }
+
+fn json_response<T: Serialize>(value: &T) -> HttpResponse {
+    // Serialize the struct to a JSON string
+    let body = match serde_json::to_string(value) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to serialize response: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // Create an HTTP response with JSON content type
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(body)
+}
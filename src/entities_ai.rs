@@ -85,68 +85,6 @@ pub struct AISearchResultValue {
     pub app_category: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct OpenAICompleteRequestMessage {
-    #[serde(rename = "role")]
-    pub role: String,
-    #[serde(rename = "content")]
-    pub content: String,
-}
-impl OpenAICompleteRequestMessage {
-    pub fn new(role: &str, content: &str) -> Self {
-        OpenAICompleteRequestMessage {
-            role: role.to_string(),
-            content: content.to_string(),
-        }
-    }
-}
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct OpenAICompleteRequest {
-    #[serde(rename = "messages")]
-    pub messages: Vec<OpenAICompleteRequestMessage>,
-    #[serde(rename = "max_tokens")]
-    pub max_tokens: i64,
-    #[serde(rename = "temperature")]
-    pub temperature: f64,
-    #[serde(rename = "top_p")]
-    pub top_p: f64,
-    #[serde(rename = "stop")]
-    pub stop: Option<Vec<String>>,
-}
-
-//
-//  response of Open AI API
-//
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct OpenAICompletionResult {
-    pub id: Option<String>,
-    pub object: Option<String>,
-    pub created: Option<u64>,
-    pub model: Option<String>,
-    pub choices: Option<Vec<Choice>>,
-    pub usage: Option<Usage>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Choice {
-    pub index: Option<u32>,
-    pub message: Option<Message>,
-    pub finish_reason: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Message {
-    pub role: Option<String>,
-    pub content: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Usage {
-    pub prompt_tokens: Option<u32>,
-    pub completion_tokens: Option<u32>,
-    pub total_tokens: Option<u32>,
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AISearchSemantics {
     #[serde(rename = "select_fields")]
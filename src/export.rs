@@ -1,40 +1,556 @@
+use std::collections::HashSet;
+
 use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::entities::{APIError, ExportFormat, SearchKafkaResponse};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportNodeKind {
+    App,
+    Topic,
+    ConsumerGroup,
+}
+
+impl ExportNodeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportNodeKind::App => "app",
+            ExportNodeKind::Topic => "topic",
+            ExportNodeKind::ConsumerGroup => "consumer_group",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportNode {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "kind")]
+    pub kind: ExportNodeKind,
+    #[serde(rename = "name")]
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportEdge {
+    #[serde(rename = "source")]
+    pub source: String,
+    #[serde(rename = "target")]
+    pub target: String,
+}
+
+/// Owner app -> topic -> consumer group -> consumer app chains of search results, each node
+/// and edge once, in the order they first appear.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExportGraph {
+    #[serde(rename = "nodes")]
+    pub nodes: Vec<ExportNode>,
+    #[serde(rename = "edges")]
+    pub edges: Vec<ExportEdge>,
+}
+
+impl ExportGraph {
+    pub fn from_rows(rows: &[SearchKafkaResponse]) -> Self {
+        let mut graph = ExportGraph::default();
+        let mut node_ids = HashSet::new();
+        let mut edge_ids = HashSet::new();
+        for row in rows {
+            // empty names, e.g. a topic nobody consumes, are left out of the chain
+            let chain = [
+                (ExportNodeKind::App, &row.app_owner),
+                (ExportNodeKind::Topic, &row.topic_name),
+                (ExportNodeKind::ConsumerGroup, &row.consumer_group_id),
+                (ExportNodeKind::App, &row.consumer_app),
+            ];
+            let mut previous: Option<String> = None;
+            for (kind, name) in chain.into_iter().filter(|(_, name)| !name.is_empty()) {
+                let id = export_node_id(kind, name);
+                if node_ids.insert(id.clone()) {
+                    graph.nodes.push(ExportNode {
+                        id: id.clone(),
+                        kind,
+                        name: name.clone(),
+                    });
+                }
+                if let Some(source) = previous {
+                    if edge_ids.insert((source.clone(), id.clone())) {
+                        graph.edges.push(ExportEdge {
+                            source,
+                            target: id.clone(),
+                        });
+                    }
+                }
+                previous = Some(id);
+            }
+        }
+        graph
+    }
+}
+
+// Letters, digits and underscores only, so an id needs no quoting in any format, and the same
+// name always gets the same id. Names that lose characters get a hash of the original name so
+// `a.b` and `a-b` stay apart.
+fn export_node_id(kind: ExportNodeKind, name: &str) -> String {
+    let prefix = match kind {
+        ExportNodeKind::App => "app",
+        ExportNodeKind::Topic => "topic",
+        ExportNodeKind::ConsumerGroup => "group",
+    };
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if sanitized == name {
+        format!("{}_{}", prefix, sanitized)
+    } else {
+        format!("{}_{}_{:08x}", prefix, sanitized, name_hash(name))
+    }
+}
+
+// 32 bit FNV-1a, unlike the std hashers it is the same in every build
+fn name_hash(name: &str) -> u32 {
+    name.bytes().fold(0x811c9dc5, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// Renders search results in one diagram or graph format.
+pub trait GraphExporter {
+    fn content_type(&self) -> &'static str;
+    fn export(&self, rows: &[SearchKafkaResponse]) -> Result<String, APIError>;
+}
+
+pub fn exporter(format: ExportFormat) -> Box<dyn GraphExporter> {
+    match format {
+        ExportFormat::Mermaid => Box::new(MermaidExporter),
+        ExportFormat::Dot => Box::new(DotExporter),
+        ExportFormat::PlantUml => Box::new(PlantUmlExporter),
+        ExportFormat::GraphMl => Box::new(GraphMlExporter),
+        ExportFormat::Json => Box::new(JsonExporter),
+    }
+}
+
+/// Mermaid flowchart, names go in quoted labels and node types are styled with `classDef`.
+pub struct MermaidExporter;
+
+impl GraphExporter for MermaidExporter {
+    fn content_type(&self) -> &'static str {
+        "text/plain"
+    }
+
+    fn export(&self, rows: &[SearchKafkaResponse]) -> Result<String, APIError> {
+        let graph = ExportGraph::from_rows(rows);
+        let mut content = String::from("flowchart LR\n");
+        content.push_str("  classDef app fill:#fff,stroke:#333,stroke-width:1px,color:#000;\n");
+        content.push_str("  classDef topic fill:#f9f,stroke:#333,stroke-width:2px,color:#fff;\n");
+        content.push_str(
+            "  classDef consumer_group fill:#bbf,stroke:#333,stroke-width:2px,color:#000;\n",
+        );
+        for node in &graph.nodes {
+            let label = mermaid_label(&node.name);
+            let shape = match node.kind {
+                ExportNodeKind::App => format!("[\"{}\"]", label),
+                ExportNodeKind::Topic => format!("[[\"{}\"]]", label),
+                ExportNodeKind::ConsumerGroup => format!("([\"{}\"])", label),
+            };
+            content.push_str(&format!(
+                "  {}{}:::{}\n",
+                node.id,
+                shape,
+                node.kind.as_str()
+            ));
+        }
+        for edge in &graph.edges {
+            content.push_str(&format!("  {} --> {}\n", edge.source, edge.target));
+        }
+        debug!(
+            "Mermaid flowchart: {} nodes, {} edges",
+            graph.nodes.len(),
+            graph.edges.len()
+        );
+        Ok(content)
+    }
+}
+
+// Mermaid reads `#...;` as an entity code inside a quoted label, so `#` is escaped as well
+fn mermaid_label(name: &str) -> String {
+    name.replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+        .replace('\n', " ")
+}
+
+/// Graphviz digraph, every id quoted so any name is valid.
+pub struct DotExporter;
+
+impl GraphExporter for DotExporter {
+    fn content_type(&self) -> &'static str {
+        "text/vnd.graphviz"
+    }
+
+    fn export(&self, rows: &[SearchKafkaResponse]) -> Result<String, APIError> {
+        let graph = ExportGraph::from_rows(rows);
+        let mut content = String::from("digraph kafka {\n  rankdir=LR;\n");
+        for node in &graph.nodes {
+            let shape = match node.kind {
+                ExportNodeKind::App => "box",
+                ExportNodeKind::Topic => "cds",
+                ExportNodeKind::ConsumerGroup => "ellipse",
+            };
+            content.push_str(&format!(
+                "  {} [label={}, shape={}];\n",
+                dot_quote(&node.id),
+                dot_quote(&node.name),
+                shape
+            ));
+        }
+        for edge in &graph.edges {
+            content.push_str(&format!(
+                "  {} -> {};\n",
+                dot_quote(&edge.source),
+                dot_quote(&edge.target)
+            ));
+        }
+        content.push_str("}\n");
+        Ok(content)
+    }
+}
+
+fn dot_quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// PlantUML component diagram, names go in quoted labels and node ids are the aliases.
+pub struct PlantUmlExporter;
+
+impl GraphExporter for PlantUmlExporter {
+    fn content_type(&self) -> &'static str {
+        "text/plain"
+    }
 
-use crate::entities::FlowChartItem;
+    fn export(&self, rows: &[SearchKafkaResponse]) -> Result<String, APIError> {
+        let graph = ExportGraph::from_rows(rows);
+        let mut content = String::from("@startuml\nleft to right direction\n");
+        for node in &graph.nodes {
+            let element = match node.kind {
+                ExportNodeKind::App => "component",
+                ExportNodeKind::Topic => "queue",
+                ExportNodeKind::ConsumerGroup => "collections",
+            };
+            content.push_str(&format!(
+                "{} \"{}\" as {}\n",
+                element,
+                plantuml_label(&node.name),
+                node.id
+            ));
+        }
+        for edge in &graph.edges {
+            content.push_str(&format!("{} --> {}\n", edge.source, edge.target));
+        }
+        content.push_str("@enduml\n");
+        Ok(content)
+    }
+}
+
+// PlantUML has no escape for a double quote inside a quoted label
+fn plantuml_label(name: &str) -> String {
+    name.replace('"', "'").replace('\n', " ")
+}
+
+/// GraphML for architecture tools, with the node kind and name as data keys.
+pub struct GraphMlExporter;
+
+impl GraphExporter for GraphMlExporter {
+    fn content_type(&self) -> &'static str {
+        "application/xml"
+    }
+
+    fn export(&self, rows: &[SearchKafkaResponse]) -> Result<String, APIError> {
+        let graph = ExportGraph::from_rows(rows);
+        let mut content = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>\n",
+            "  <graph id=\"kafka\" edgedefault=\"directed\">\n",
+        ));
+        for node in &graph.nodes {
+            content.push_str(&format!(
+                "    <node id=\"{}\">\n      <data key=\"kind\">{}</data>\n      <data key=\"name\">{}</data>\n    </node>\n",
+                xml_escape(&node.id),
+                node.kind.as_str(),
+                xml_escape(&node.name)
+            ));
+        }
+        for (i, edge) in graph.edges.iter().enumerate() {
+            content.push_str(&format!(
+                "    <edge id=\"e{}\" source=\"{}\" target=\"{}\"/>\n",
+                i,
+                xml_escape(&edge.source),
+                xml_escape(&edge.target)
+            ));
+        }
+        content.push_str("  </graph>\n</graphml>\n");
+        Ok(content)
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
 
-pub fn export_mm_file<T: Into<FlowChartItem>>(
-    dataset: Vec<T>,
-    _path: &str,
-) -> std::io::Result<String> {
-    //let mut file = std::fs::File::create(path)?;
-    let mut content = String::new();
+pub struct JsonExporter;
 
-    let content_header = "flowchart LR;";
-    //writeln!(file, "{}", content_header)?;
-    content.push_str(content_header);
-    content.push_str("\n");
+impl GraphExporter for JsonExporter {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn export(&self, rows: &[SearchKafkaResponse]) -> Result<String, APIError> {
+        serde_json::to_string(&ExportGraph::from_rows(rows))
+            .map_err(|e| APIError::new(&format!("Failed to export to JSON: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(app_owner: &str, topic: &str, group: &str, consumer_app: &str) -> SearchKafkaResponse {
+        SearchKafkaResponse {
+            app_owner: app_owner.to_string(),
+            topic_name: topic.to_string(),
+            consumer_group_id: group.to_string(),
+            consumer_app: consumer_app.to_string(),
+            description: String::new(),
+        }
+    }
 
-    for item in dataset {
-        let item: FlowChartItem = item.into();
+    #[test]
+    fn content_type_per_format() {
+        let content_types: Vec<&str> = [
+            ExportFormat::Mermaid,
+            ExportFormat::Dot,
+            ExportFormat::PlantUml,
+            ExportFormat::GraphMl,
+            ExportFormat::Json,
+        ]
+        .into_iter()
+        .map(|format| exporter(format).content_type())
+        .collect();
+        assert_eq!(
+            content_types,
+            [
+                "text/plain",
+                "text/vnd.graphviz",
+                "text/plain",
+                "application/xml",
+                "application/json"
+            ]
+        );
+    }
 
-        let data = format!("{};", item.to_print_string());
-        let style_topic = format!(
-            "style {} fill:#f9f,stroke:#333,stroke-width:2px,color:#fff;",
-            item.kafka_topic
+    #[test]
+    fn dot_quote_escapes_backslashes_quotes_and_newlines() {
+        assert_eq!(dot_quote("payments"), "\"payments\"");
+        assert_eq!(
+            dot_quote("o\"neil\\ops\nteam"),
+            "\"o\\\"neil\\\\ops\\nteam\""
         );
-        let style_consumer = format!(
-            "style {} fill:#bbf,stroke:#333,stroke-width:2px,color:#000;",
-            item.consumer_group
+        // `<` and `&` mean nothing in a quoted id
+        assert_eq!(dot_quote("a<b>&c"), "\"a<b>&c\"");
+    }
+
+    #[test]
+    fn dot_export_quotes_names_in_labels() {
+        let content = DotExporter
+            .export(&[row("o\"neil\\ops", "payments.txn", "", "")])
+            .unwrap();
+        let owner = export_node_id(ExportNodeKind::App, "o\"neil\\ops");
+        assert_eq!(
+            content,
+            format!(
+                "digraph kafka {{\n  rankdir=LR;\n  \"{}\" [label=\"o\\\"neil\\\\ops\", shape=box];\n  \"topic_payments_txn_{:08x}\" [label=\"payments.txn\", shape=cds];\n  \"{}\" -> \"topic_payments_txn_{:08x}\";\n}}\n",
+                owner,
+                name_hash("payments.txn"),
+                owner,
+                name_hash("payments.txn")
+            )
         );
+    }
 
-        content.push_str(format!("  {}", &data).as_str());
-        content.push_str("\n");
-        content.push_str(&style_topic);
-        content.push_str("\n");
-        content.push_str(&style_consumer);
-        content.push_str("\n");
+    #[test]
+    fn plantuml_label_replaces_quotes_and_newlines() {
+        assert_eq!(plantuml_label("say \"hi\"\nnow"), "say 'hi' now");
+        assert_eq!(plantuml_label("a\\b <&>"), "a\\b <&>");
     }
-    debug!("content : \n{}", content);
 
-    Ok(content)
+    #[test]
+    fn plantuml_export_uses_ids_as_aliases() {
+        let content = PlantUmlExporter
+            .export(&[row("ops \"core\"", "audit", "", "")])
+            .unwrap();
+        let owner = export_node_id(ExportNodeKind::App, "ops \"core\"");
+        assert_eq!(
+            content,
+            format!(
+                "@startuml\nleft to right direction\ncomponent \"ops 'core'\" as {}\nqueue \"audit\" as topic_audit\n{} --> topic_audit\n@enduml\n",
+                owner, owner
+            )
+        );
+    }
+
+    #[test]
+    fn xml_escape_escapes_markup_characters() {
+        assert_eq!(xml_escape("a<b>&\"c'"), "a&lt;b&gt;&amp;&quot;c&apos;");
+        // entities are not decoded first, `&amp;` is escaped again
+        assert_eq!(xml_escape("&amp;"), "&amp;amp;");
+        assert_eq!(xml_escape("line\nbreak\\"), "line\nbreak\\");
+    }
+
+    #[test]
+    fn graphml_export_escapes_names() {
+        let content = GraphMlExporter
+            .export(&[row("r&d <labs>", "audit", "", "")])
+            .unwrap();
+        let owner = export_node_id(ExportNodeKind::App, "r&d <labs>");
+        assert!(content.contains(&format!(
+            "    <node id=\"{}\">\n      <data key=\"kind\">app</data>\n      <data key=\"name\">r&amp;d &lt;labs&gt;</data>\n    </node>\n",
+            owner
+        )));
+        assert!(content.contains(&format!(
+            "    <edge id=\"e0\" source=\"{}\" target=\"topic_audit\"/>\n",
+            owner
+        )));
+        assert!(!content.contains("r&d"));
+    }
+
+    #[test]
+    fn shared_topic_is_one_node() {
+        let graph = ExportGraph::from_rows(&[
+            row("payments", "payments", "ledger-g", "ledger"),
+            row("payments", "payments", "fraud-g", "fraud"),
+        ]);
+        let nodes: Vec<(ExportNodeKind, &str)> = graph
+            .nodes
+            .iter()
+            .map(|n| (n.kind, n.name.as_str()))
+            .collect();
+        // an app and a topic with the same name are different nodes
+        assert_eq!(
+            nodes,
+            [
+                (ExportNodeKind::App, "payments"),
+                (ExportNodeKind::Topic, "payments"),
+                (ExportNodeKind::ConsumerGroup, "ledger-g"),
+                (ExportNodeKind::App, "ledger"),
+                (ExportNodeKind::ConsumerGroup, "fraud-g"),
+                (ExportNodeKind::App, "fraud"),
+            ]
+        );
+    }
+
+    #[test]
+    fn edges_are_unique() {
+        let rows = [
+            row("payments", "txn", "ledger", "ledger"),
+            row("payments", "txn", "ledger", "ledger"),
+            row("payments", "refund", "", ""),
+        ];
+        let graph = ExportGraph::from_rows(&rows);
+        let edges: Vec<(&str, &str)> = graph
+            .edges
+            .iter()
+            .map(|e| (e.source.as_str(), e.target.as_str()))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                ("app_payments", "topic_txn"),
+                ("topic_txn", "group_ledger"),
+                ("group_ledger", "app_ledger"),
+                ("app_payments", "topic_refund"),
+            ]
+        );
+    }
+
+    #[test]
+    fn node_ids_are_letters_digits_and_underscores() {
+        for name in [
+            "payments.txn-events.v1",
+            "o'neil \"ops\"",
+            "r&d <labs>",
+            "é#1\n",
+        ] {
+            for kind in [
+                ExportNodeKind::App,
+                ExportNodeKind::Topic,
+                ExportNodeKind::ConsumerGroup,
+            ] {
+                let id = export_node_id(kind, name);
+                assert!(
+                    id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                    "{}",
+                    id
+                );
+                assert_eq!(id, export_node_id(kind, name));
+            }
+        }
+        assert_eq!(
+            export_node_id(ExportNodeKind::Topic, "audit"),
+            "topic_audit"
+        );
+        assert_eq!(
+            export_node_id(ExportNodeKind::ConsumerGroup, "g1"),
+            "group_g1"
+        );
+    }
+
+    #[test]
+    fn names_sanitized_alike_get_different_ids() {
+        let dotted = export_node_id(ExportNodeKind::Topic, "a.b");
+        let dashed = export_node_id(ExportNodeKind::Topic, "a-b");
+        let underscored = export_node_id(ExportNodeKind::Topic, "a_b");
+        assert!(dotted.starts_with("topic_a_b_"));
+        assert!(dashed.starts_with("topic_a_b_"));
+        assert_ne!(dotted, dashed);
+        assert_ne!(dotted, underscored);
+        assert_ne!(dashed, underscored);
+
+        let graph = ExportGraph::from_rows(&[row("app", "a.b", "", ""), row("app", "a-b", "", "")]);
+        assert_eq!(graph.nodes.len(), 3);
+    }
+
+    #[test]
+    fn mermaid_label_escapes_hash_and_quotes() {
+        assert_eq!(mermaid_label("payments"), "payments");
+        assert_eq!(mermaid_label("team #1"), "team #35;1");
+        assert_eq!(mermaid_label("say \"hi\""), "say #quot;hi#quot;");
+        assert_eq!(mermaid_label("<ops>\nteam"), "#lt;ops#gt; team");
+        // the `#` of an escape is not escaped again
+        assert_eq!(mermaid_label("#\""), "#35;#quot;");
+    }
+
+    #[test]
+    fn mermaid_export_quotes_labels() {
+        let content = MermaidExporter
+            .export(&[row("team #1", "audit", "", "")])
+            .unwrap();
+        let owner = export_node_id(ExportNodeKind::App, "team #1");
+        assert!(content.contains(&format!("  {}[\"team #35;1\"]:::app\n", owner)));
+        assert!(content.contains("  topic_audit[[\"audit\"]]:::topic\n"));
+        assert!(content.contains(&format!("  {} --> topic_audit\n", owner)));
+    }
 }
@@ -0,0 +1,489 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use polars::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{consumer_bindings_from_frame, topics_from_frame};
+use crate::drift_report::declared_rows;
+use crate::entities::APIError;
+
+// lowercase words separated by dots, dashes or underscores
+const DEFAULT_NAME_PATTERN: &str = "^[a-z0-9]+([._-][a-z0-9]+)*$";
+
+/// Naming conventions of topics and consumer groups.
+#[derive(Clone)]
+pub struct NamingRules {
+    topic: Regex,
+    consumer_group: Regex,
+}
+
+impl NamingRules {
+    /// Read the `TOPIC_NAME_PATTERN` and `CONSUMER_GROUP_NAME_PATTERN` regular expressions,
+    /// both default to lowercase words separated by `.`, `-` or `_`.
+    pub fn from_env() -> Result<NamingRules, APIError> {
+        Ok(NamingRules {
+            topic: pattern_from_env("TOPIC_NAME_PATTERN")?,
+            consumer_group: pattern_from_env("CONSUMER_GROUP_NAME_PATTERN")?,
+        })
+    }
+}
+
+fn pattern_from_env(name: &str) -> Result<Regex, APIError> {
+    let pattern = std::env::var(name).unwrap_or(DEFAULT_NAME_PATTERN.to_string());
+    Regex::new(&pattern).map_err(|e| {
+        APIError::new(&format!(
+            "{} is not a valid regular expression: {}",
+            name, e
+        ))
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrphanTopic {
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    #[serde(rename = "app_owner")]
+    pub app_owner: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnknownTopicConsumer {
+    #[serde(rename = "consumer_group_id")]
+    pub consumer_group_id: String,
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    #[serde(rename = "consumer_app")]
+    pub consumer_app: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SharedConsumerGroup {
+    #[serde(rename = "consumer_group_id")]
+    pub consumer_group_id: String,
+    #[serde(rename = "consumer_apps")]
+    pub consumer_apps: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SelfConsumption {
+    #[serde(rename = "app_name")]
+    pub app_name: String,
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    #[serde(rename = "consumer_group_id")]
+    pub consumer_group_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NamingViolation {
+    // topic or consumer_group
+    #[serde(rename = "kind")]
+    pub kind: String,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "app_name")]
+    pub app_name: String,
+    #[serde(rename = "pattern")]
+    pub pattern: String,
+}
+
+/// Inconsistencies within the declared inventory and consumer datasets.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HealthReport {
+    // declared topics nobody consumes
+    #[serde(rename = "orphan_topics")]
+    pub orphan_topics: Vec<OrphanTopic>,
+    // consumers of topics that aren't in the inventory
+    #[serde(rename = "unknown_topic_consumers")]
+    pub unknown_topic_consumers: Vec<UnknownTopicConsumer>,
+    // consumer group ids used by more than one app
+    #[serde(rename = "shared_consumer_groups")]
+    pub shared_consumer_groups: Vec<SharedConsumerGroup>,
+    // apps consuming the topics they produce
+    #[serde(rename = "self_consumption")]
+    pub self_consumption: Vec<SelfConsumption>,
+    #[serde(rename = "naming_violations")]
+    pub naming_violations: Vec<NamingViolation>,
+}
+
+// One finding of the report, flattened for the CSV download
+#[derive(Serialize)]
+struct HealthCsvRow<'a> {
+    #[serde(rename = "check")]
+    check: &'a str,
+    #[serde(rename = "app_name")]
+    app_name: &'a str,
+    #[serde(rename = "topic_name")]
+    topic_name: &'a str,
+    #[serde(rename = "consumer_group_id")]
+    consumer_group_id: &'a str,
+    #[serde(rename = "detail")]
+    detail: String,
+}
+
+pub fn health_report(
+    ds_inventory: &DataFrame,
+    ds_consumer: &DataFrame,
+    rules: &NamingRules,
+) -> Result<HealthReport, APIError> {
+    let topics = topics_from_frame(&declared_rows(ds_inventory)?)?;
+    let bindings = consumer_bindings_from_frame(&declared_rows(ds_consumer)?)?;
+
+    let owners: BTreeMap<&str, &str> = topics
+        .iter()
+        .map(|t| (t.name.as_str(), t.project.as_str()))
+        .collect();
+    let consumed: BTreeSet<&str> = bindings.iter().map(|b| b.topic_name.as_str()).collect();
+
+    let orphan_topics = owners
+        .iter()
+        .filter(|(topic, _)| !consumed.contains(*topic))
+        .map(|(topic, owner)| OrphanTopic {
+            topic_name: topic.to_string(),
+            app_owner: owner.to_string(),
+        })
+        .collect();
+
+    let mut unknown_topic_consumers = Vec::new();
+    let mut self_consumption = Vec::new();
+    // group id -> apps using it
+    let mut group_apps: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for binding in &bindings {
+        match owners.get(binding.topic_name.as_str()) {
+            None => unknown_topic_consumers.push(UnknownTopicConsumer {
+                consumer_group_id: binding.consumer_group.clone(),
+                topic_name: binding.topic_name.clone(),
+                consumer_app: binding.consumer_app.clone(),
+            }),
+            Some(owner) if !owner.is_empty() && *owner == binding.consumer_app => {
+                self_consumption.push(SelfConsumption {
+                    app_name: binding.consumer_app.clone(),
+                    topic_name: binding.topic_name.clone(),
+                    consumer_group_id: binding.consumer_group.clone(),
+                });
+            }
+            Some(_) => {}
+        }
+        if !binding.consumer_group.is_empty() {
+            group_apps
+                .entry(binding.consumer_group.as_str())
+                .or_default()
+                .insert(binding.consumer_app.as_str());
+        }
+    }
+
+    let shared_consumer_groups = group_apps
+        .into_iter()
+        .filter(|(_, apps)| apps.len() > 1)
+        .map(|(group, apps)| SharedConsumerGroup {
+            consumer_group_id: group.to_string(),
+            consumer_apps: apps.into_iter().map(|a| a.to_string()).collect(),
+        })
+        .collect();
+
+    let mut naming_violations = Vec::new();
+    for (topic, owner) in &owners {
+        if !rules.topic.is_match(topic) {
+            naming_violations.push(NamingViolation {
+                kind: "topic".to_string(),
+                name: topic.to_string(),
+                app_name: owner.to_string(),
+                pattern: rules.topic.as_str().to_string(),
+            });
+        }
+    }
+    let groups: BTreeMap<&str, &str> = bindings
+        .iter()
+        .filter(|b| !b.consumer_group.is_empty())
+        .map(|b| (b.consumer_group.as_str(), b.consumer_app.as_str()))
+        .collect();
+    for (group, app) in groups {
+        if !rules.consumer_group.is_match(group) {
+            naming_violations.push(NamingViolation {
+                kind: "consumer_group".to_string(),
+                name: group.to_string(),
+                app_name: app.to_string(),
+                pattern: rules.consumer_group.as_str().to_string(),
+            });
+        }
+    }
+
+    Ok(HealthReport {
+        orphan_topics,
+        unknown_topic_consumers,
+        shared_consumer_groups,
+        self_consumption,
+        naming_violations,
+    })
+}
+
+impl HealthReport {
+    /// One row per finding, with a `check` column naming the list it comes from.
+    pub fn to_csv(&self) -> Result<String, APIError> {
+        let mut rows = Vec::new();
+        for t in &self.orphan_topics {
+            rows.push(HealthCsvRow {
+                check: "orphan_topic",
+                app_name: &t.app_owner,
+                topic_name: &t.topic_name,
+                consumer_group_id: "",
+                detail: "No consumers".to_string(),
+            });
+        }
+        for c in &self.unknown_topic_consumers {
+            rows.push(HealthCsvRow {
+                check: "unknown_topic_consumer",
+                app_name: &c.consumer_app,
+                topic_name: &c.topic_name,
+                consumer_group_id: &c.consumer_group_id,
+                detail: "Topic not in the inventory".to_string(),
+            });
+        }
+        for g in &self.shared_consumer_groups {
+            rows.push(HealthCsvRow {
+                check: "shared_consumer_group",
+                app_name: "",
+                topic_name: "",
+                consumer_group_id: &g.consumer_group_id,
+                detail: format!("Used by {}", g.consumer_apps.join(", ")),
+            });
+        }
+        for s in &self.self_consumption {
+            rows.push(HealthCsvRow {
+                check: "self_consumption",
+                app_name: &s.app_name,
+                topic_name: &s.topic_name,
+                consumer_group_id: &s.consumer_group_id,
+                detail: "Consumes its own topic".to_string(),
+            });
+        }
+        for v in &self.naming_violations {
+            let (topic_name, consumer_group_id) = match v.kind.as_str() {
+                "topic" => (v.name.as_str(), ""),
+                _ => ("", v.name.as_str()),
+            };
+            rows.push(HealthCsvRow {
+                check: "naming_violation",
+                app_name: &v.app_name,
+                topic_name,
+                consumer_group_id,
+                detail: format!("Does not match {}", v.pattern),
+            });
+        }
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        // serialize only writes the header with the first row
+        if rows.is_empty() {
+            writer
+                .write_record([
+                    "check",
+                    "app_name",
+                    "topic_name",
+                    "consumer_group_id",
+                    "detail",
+                ])
+                .map_err(|e| APIError::new(&format!("Failed to write CSV: {}", e)))?;
+        }
+        for row in rows {
+            writer
+                .serialize(row)
+                .map_err(|e| APIError::new(&format!("Failed to write CSV: {}", e)))?;
+        }
+        let bytes = writer
+            .into_inner()
+            .map_err(|e| APIError::new(&format!("Failed to write CSV: {}", e)))?;
+        String::from_utf8(bytes).map_err(|e| APIError::new(&format!("Failed to write CSV: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_service::{
+        COL_APP_OWNER_INVENTORY_FILE, COL_CONSUMER_APP_NAME_CONSUMER_FILE,
+        COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE,
+        COL_TOPIC_NAME_INVENTORY_FILE,
+    };
+
+    fn default_rules() -> NamingRules {
+        NamingRules {
+            topic: Regex::new(DEFAULT_NAME_PATTERN).unwrap(),
+            consumer_group: Regex::new(DEFAULT_NAME_PATTERN).unwrap(),
+        }
+    }
+
+    // `topics` are (owner, topic), `consumers` are (app, topic, group)
+    fn report(topics: &[(&str, &str)], consumers: &[(&str, &str, &str)]) -> HealthReport {
+        let ds_inventory = df!(
+            COL_APP_OWNER_INVENTORY_FILE => topics.iter().map(|t| t.0).collect::<Vec<_>>(),
+            COL_TOPIC_NAME_INVENTORY_FILE => topics.iter().map(|t| t.1).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let ds_consumer = df!(
+            COL_CONSUMER_APP_NAME_CONSUMER_FILE => consumers.iter().map(|c| c.0).collect::<Vec<_>>(),
+            COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE => consumers.iter().map(|c| c.1).collect::<Vec<_>>(),
+            COL_CONSUMER_GROUP_NAME_CONSUMER_FILE => consumers.iter().map(|c| c.2).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        health_report(&ds_inventory, &ds_consumer, &default_rules()).unwrap()
+    }
+
+    fn is_clean(report: &HealthReport) -> bool {
+        report.orphan_topics.is_empty()
+            && report.unknown_topic_consumers.is_empty()
+            && report.shared_consumer_groups.is_empty()
+            && report.self_consumption.is_empty()
+            && report.naming_violations.is_empty()
+    }
+
+    #[test]
+    fn consistent_datasets_have_no_findings() {
+        let report = report(
+            &[("payments", "payments.txn")],
+            &[("ledger", "payments.txn", "ledger-txn")],
+        );
+        assert!(is_clean(&report));
+    }
+
+    #[test]
+    fn topic_without_consumers_is_an_orphan() {
+        let report = report(
+            &[
+                ("payments", "payments.txn"),
+                ("payments", "payments.refund"),
+            ],
+            &[("ledger", "payments.txn", "ledger-txn")],
+        );
+        assert_eq!(report.orphan_topics.len(), 1);
+        assert_eq!(report.orphan_topics[0].topic_name, "payments.refund");
+        assert_eq!(report.orphan_topics[0].app_owner, "payments");
+    }
+
+    #[test]
+    fn consumer_of_a_topic_missing_from_the_inventory() {
+        let report = report(
+            &[("payments", "payments.txn")],
+            &[
+                ("ledger", "payments.txn", "ledger-txn"),
+                ("ledger", "legacy.feed", "ledger-legacy"),
+            ],
+        );
+        assert_eq!(report.unknown_topic_consumers.len(), 1);
+        let unknown = &report.unknown_topic_consumers[0];
+        assert_eq!(
+            (
+                unknown.consumer_app.as_str(),
+                unknown.topic_name.as_str(),
+                unknown.consumer_group_id.as_str()
+            ),
+            ("ledger", "legacy.feed", "ledger-legacy")
+        );
+    }
+
+    #[test]
+    fn group_used_by_two_apps_is_shared() {
+        let report = report(
+            &[
+                ("payments", "payments.txn"),
+                ("payments", "payments.refund"),
+            ],
+            &[
+                ("ledger", "payments.txn", "payments-readers"),
+                ("fraud", "payments.refund", "payments-readers"),
+                ("fraud", "payments.txn", "fraud-txn"),
+            ],
+        );
+        assert_eq!(report.shared_consumer_groups.len(), 1);
+        assert_eq!(
+            report.shared_consumer_groups[0].consumer_group_id,
+            "payments-readers"
+        );
+        assert_eq!(
+            report.shared_consumer_groups[0].consumer_apps,
+            ["fraud", "ledger"]
+        );
+    }
+
+    #[test]
+    fn app_consuming_its_own_topic() {
+        let report = report(
+            &[("payments", "payments.txn")],
+            &[
+                ("payments", "payments.txn", "payments-replay"),
+                ("ledger", "payments.txn", "ledger-txn"),
+            ],
+        );
+        assert_eq!(report.self_consumption.len(), 1);
+        let found = &report.self_consumption[0];
+        assert_eq!(
+            (
+                found.app_name.as_str(),
+                found.topic_name.as_str(),
+                found.consumer_group_id.as_str()
+            ),
+            ("payments", "payments.txn", "payments-replay")
+        );
+    }
+
+    #[test]
+    fn names_not_matching_the_default_pattern() {
+        let report = report(
+            &[
+                ("payments", "Payments.TXN"),
+                ("payments", "payments..refund"),
+            ],
+            &[
+                ("ledger", "Payments.TXN", "Ledger Group"),
+                ("ledger", "payments..refund", "ledger_refund-v2"),
+            ],
+        );
+        let violations: Vec<(&str, &str, &str)> = report
+            .naming_violations
+            .iter()
+            .map(|v| (v.kind.as_str(), v.name.as_str(), v.app_name.as_str()))
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                ("topic", "Payments.TXN", "payments"),
+                ("topic", "payments..refund", "payments"),
+                ("consumer_group", "Ledger Group", "ledger"),
+            ]
+        );
+        assert_eq!(report.naming_violations[0].pattern, DEFAULT_NAME_PATTERN);
+    }
+
+    #[test]
+    fn csv_of_an_empty_report_has_the_header() {
+        let report = report(
+            &[("payments", "payments.txn")],
+            &[("ledger", "payments.txn", "ledger-txn")],
+        );
+        assert_eq!(
+            report.to_csv().unwrap(),
+            "check,app_name,topic_name,consumer_group_id,detail\n"
+        );
+    }
+
+    #[test]
+    fn csv_has_a_row_per_finding() {
+        let report = report(
+            &[
+                ("payments", "payments.txn"),
+                ("payments", "payments.refund"),
+            ],
+            &[
+                ("payments", "payments.txn", "payments-replay"),
+                ("ledger", "payments.txn", "payments-replay"),
+            ],
+        );
+        assert_eq!(
+            report.to_csv().unwrap(),
+            "check,app_name,topic_name,consumer_group_id,detail\n\
+             orphan_topic,payments,payments.refund,,No consumers\n\
+             shared_consumer_group,,,payments-replay,\"Used by ledger, payments\"\n\
+             self_consumption,payments,payments.txn,payments-replay,Consumes its own topic\n"
+        );
+    }
+}
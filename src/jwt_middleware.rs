@@ -1,23 +1,61 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
+use actix_web::body::EitherBody;
 use actix_web::dev::{Service, Transform};
 use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error, HttpMessage, Result};
 use futures_util::future::{ok, Ready};
 use futures_util::future::{FutureExt, LocalBoxFuture};
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use jsonwebtoken::decode_header;
 use log::{debug, error};
 
-use crate::entities::{AuthError, Claims};
+use crate::api_keys::{ApiKeyStore, API_KEY_PREFIX};
+use crate::authorization::Role;
+use crate::entities::{APIError, Claims, ErrorCode};
+use crate::oidc::OidcProvider;
+use crate::signing_keys::KeyRing;
+use crate::token_store::TokenStore;
 
+/// Accepts the tokens issued by `apis::login` and, if an OIDC issuer is configured,
+/// ID tokens signed by the issuer, or an API key in `X-API-Key`; rejects revoked tokens and
+/// tokens without the viewer role. Routes needing more are wrapped in `RequireRole`.
 pub struct JwtMiddleware {
-    secret: String,
+    keys: Arc<KeyRing>,
+    oidc: Option<Arc<OidcProvider>>,
+    token_store: Arc<TokenStore>,
+    api_keys: Arc<ApiKeyStore>,
 }
 
 impl JwtMiddleware {
-    pub fn new(secret: String) -> Self {
-        JwtMiddleware { secret }
+    pub fn new(
+        keys: Arc<KeyRing>,
+        oidc: Option<Arc<OidcProvider>>,
+        token_store: Arc<TokenStore>,
+        api_keys: Arc<ApiKeyStore>,
+    ) -> Self {
+        JwtMiddleware {
+            keys,
+            oidc,
+            token_store,
+            api_keys,
+        }
+    }
+}
+
+async fn validate_token(
+    token: &str,
+    keys: &KeyRing,
+    oidc: Option<&OidcProvider>,
+) -> Result<Claims, String> {
+    let header = decode_header(token).map_err(|e| e.to_string())?;
+    if keys.is_local(header.alg, header.kid.as_deref()) {
+        return keys.verify(token, true);
+    }
+    match oidc {
+        Some(oidc) => oidc.validate(token, None).await.map_err(|e| e.to_string()),
+        None => Err(format!("Unknown signing key {:?}", header.kid)),
     }
 }
 
@@ -27,7 +65,7 @@ where
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Transform = JwtMiddlewareMiddleware<S>;
     type InitError = ();
@@ -36,14 +74,20 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(JwtMiddlewareMiddleware {
             service: Rc::new(RefCell::new(service)),
-            secret: self.secret.clone(),
+            keys: self.keys.clone(),
+            oidc: self.oidc.clone(),
+            token_store: self.token_store.clone(),
+            api_keys: self.api_keys.clone(),
         })
     }
 }
 
 pub struct JwtMiddlewareMiddleware<S> {
     service: Rc<RefCell<S>>,
-    secret: String,
+    keys: Arc<KeyRing>,
+    oidc: Option<Arc<OidcProvider>>,
+    token_store: Arc<TokenStore>,
+    api_keys: Arc<ApiKeyStore>,
 }
 
 impl<S, B> Service<ServiceRequest> for JwtMiddlewareMiddleware<S>
@@ -52,7 +96,7 @@ where
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -61,44 +105,82 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let secret = self.secret.clone();
+        let keys = self.keys.clone();
+        let oidc = self.oidc.clone();
+        let token_store = self.token_store.clone();
+        let api_keys = self.api_keys.clone();
         let svc = self.service.clone();
 
         async move {
-            return if req.path().starts_with("/api/v1") {
-                debug!("Checking JWT token");
-                if let Some(authen_header) = req.headers().get("Authorization") {
-                    debug!("Authorization");
-                    if let Ok(authen_str) = authen_header.to_str() {
-                        if authen_str.starts_with("Bearer ") {
-                            let token = &authen_str[7..];
-                            debug!("Token: {}", token);
-                            let mut validation = Validation::default();
-                            validation.set_audience(&["kafka-repo-service-aud"]);
-                            let token_data = decode::<Claims>(
-                                token,
-                                &DecodingKey::from_secret(secret.as_ref()),
-                                &validation,
-                            );
+            // the decoded path actix routes on, `/api/%761` reaches the same handlers
+            if !req.match_info().as_str().starts_with("/api/v1") {
+                return svc.call(req).await.map(ServiceResponse::map_into_left_body);
+            }
+            debug!("Checking credentials");
+            let header = |name: &str| {
+                req.headers()
+                    .get(name)
+                    .and_then(|h| h.to_str().ok())
+                    .map(|h| h.to_string())
+            };
+            let api_key = header("X-API-Key");
+            let bearer = header("Authorization")
+                .and_then(|h| h.strip_prefix("Bearer ").map(|t| t.to_string()));
 
-                            return match token_data {
-                                Ok(data) => {
-                                    debug!("Token data: {:?}", data);
-                                    req.extensions_mut().insert(data.claims);
-                                    svc.call(req).await
-                                }
-                                Err(e) => {
-                                    error!("Failed to decode token : {} ", e);
-                                    Err(AuthError::Unauthorized.into())
-                                }
-                            };
+            let claims = match (api_key, bearer) {
+                (Some(key), _) => api_keys.authenticate(&key, keys.issuer(), keys.audience()),
+                (None, Some(token)) if token.starts_with(API_KEY_PREFIX) => {
+                    api_keys.authenticate(&token, keys.issuer(), keys.audience())
+                }
+                (None, Some(token)) => validate_token(&token, &keys, oidc.as_deref())
+                    .await
+                    .and_then(|claims| {
+                        if token_store.is_revoked(&claims) {
+                            Err(format!(
+                                "Revoked token {} of {}",
+                                claims.jti(),
+                                claims.sub()
+                            ))
+                        } else {
+                            Ok(claims)
                         }
-                    }
+                    }),
+                (None, None) => {
+                    let error = APIError::with_code(ErrorCode::Unauthorized, "Missing credentials");
+                    return Ok(req.error_response(error).map_into_right_body());
                 }
-                Err(AuthError::Unauthorized.into())
-            } else {
-                svc.call(req).await
             };
+
+            match claims {
+                Ok(claims) => {
+                    debug!("Token claims: {:?}", claims);
+                    let role = Role::Viewer;
+                    if !claims.has_role(role) {
+                        error!(
+                            "{} needs the {:?} role to call {} {}",
+                            claims.sub(),
+                            role,
+                            req.method(),
+                            req.path()
+                        );
+                        let error = APIError::with_code(
+                            ErrorCode::Forbidden,
+                            &format!("The {:?} role is required", role),
+                        );
+                        return Ok(req.error_response(error).map_into_right_body());
+                    }
+                    req.extensions_mut().insert(claims);
+                    svc.call(req).await.map(ServiceResponse::map_into_left_body)
+                }
+                Err(e) => {
+                    error!("Failed to decode token : {} ", e);
+                    let error = APIError::with_code(
+                        ErrorCode::Unauthorized,
+                        "Missing, invalid or expired credentials",
+                    );
+                    Ok(req.error_response(error).map_into_right_body())
+                }
+            }
         }
         .boxed_local()
     }
@@ -0,0 +1,498 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use log::debug;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::data_service::{
+    COL_APP_OWNER_INVENTORY_FILE, COL_CONSUMER_APP_NAME_CONSUMER_FILE,
+    COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE,
+    COL_TOPIC_NAME_INVENTORY_FILE,
+};
+use crate::dataset_schema::string_values;
+use crate::entities::APIError;
+
+// Where a row of the merged inventory / consumer frames came from
+pub const COL_RECORD_SOURCE: &str = "Record_Source";
+pub const RECORD_SOURCE_INVENTORY: &str = "inventory";
+pub const RECORD_SOURCE_CLUSTER: &str = "cluster";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConsumerGroupInfo {
+    #[serde(rename = "group_id")]
+    pub group_id: String,
+    #[serde(rename = "state")]
+    pub state: String,
+    #[serde(rename = "topics")]
+    pub topics: Vec<String>,
+}
+
+/// Topics and consumer groups as reported by a cluster's metadata.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClusterSnapshot {
+    #[serde(rename = "harvested_at")]
+    pub harvested_at: DateTime<Utc>,
+    #[serde(rename = "topics")]
+    pub topics: Vec<String>,
+    #[serde(rename = "consumer_groups")]
+    pub consumer_groups: Vec<ConsumerGroupInfo>,
+}
+
+/// Read-only view of a cluster's admin API.
+pub trait ClusterAdmin: Send + Sync {
+    fn describe(&self) -> String;
+    fn snapshot(&self) -> LocalBoxFuture<'_, Result<ClusterSnapshot, APIError>>;
+}
+
+/// Serves a snapshot saved as JSON, for running offline or standing in for a real cluster in tests.
+pub struct SnapshotFileAdmin {
+    path: PathBuf,
+}
+
+impl SnapshotFileAdmin {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SnapshotFileAdmin { path: path.into() }
+    }
+}
+
+impl ClusterAdmin for SnapshotFileAdmin {
+    fn describe(&self) -> String {
+        format!("file://{}", self.path.display())
+    }
+
+    fn snapshot(&self) -> LocalBoxFuture<'_, Result<ClusterSnapshot, APIError>> {
+        async move {
+            let content = std::fs::read_to_string(&self.path).map_err(|e| {
+                APIError::new(&format!("Failed to read {}: {}", self.path.display(), e))
+            })?;
+            serde_json::from_str::<ClusterSnapshot>(&content).map_err(|e| {
+                APIError::new(&format!(
+                    "Failed to parse cluster snapshot {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            })
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(feature = "kafka")]
+pub use rdkafka_admin::RdKafkaAdmin;
+
+#[cfg(feature = "kafka")]
+mod rdkafka_admin {
+    use std::collections::BTreeSet;
+    use std::time::Duration;
+
+    use futures::future::LocalBoxFuture;
+    use futures::FutureExt;
+    use rdkafka::config::ClientConfig;
+    use rdkafka::consumer::{BaseConsumer, Consumer};
+
+    use super::{ClusterAdmin, ClusterSnapshot, ConsumerGroupInfo};
+    use crate::entities::APIError;
+
+    /// Harvests metadata from a live cluster with librdkafka.
+    pub struct RdKafkaAdmin {
+        config: ClientConfig,
+        bootstrap_servers: String,
+        timeout: Duration,
+    }
+
+    impl RdKafkaAdmin {
+        /// `properties` are extra librdkafka settings such as `security.protocol` or `sasl.*`.
+        pub fn new(bootstrap_servers: &str, properties: &[(String, String)]) -> Self {
+            let mut config = ClientConfig::new();
+            config.set("bootstrap.servers", bootstrap_servers);
+            for (key, value) in properties {
+                config.set(key, value);
+            }
+            RdKafkaAdmin {
+                config,
+                bootstrap_servers: bootstrap_servers.to_string(),
+                timeout: Duration::from_secs(30),
+            }
+        }
+
+        fn fetch(config: ClientConfig, timeout: Duration) -> Result<ClusterSnapshot, APIError> {
+            let consumer: BaseConsumer = config
+                .create()
+                .map_err(|e| APIError::new(&format!("Failed to create kafka client: {}", e)))?;
+
+            let metadata = consumer
+                .fetch_metadata(None, timeout)
+                .map_err(|e| APIError::new(&format!("Failed to fetch topic metadata: {}", e)))?;
+            let topics = metadata
+                .topics()
+                .iter()
+                .map(|t| t.name().to_string())
+                .collect();
+
+            let groups = consumer
+                .fetch_group_list(None, timeout)
+                .map_err(|e| APIError::new(&format!("Failed to list consumer groups: {}", e)))?;
+            let consumer_groups = groups
+                .groups()
+                .iter()
+                .map(|g| {
+                    let mut topics = BTreeSet::new();
+                    if g.protocol_type() == "consumer" {
+                        for member in g.members() {
+                            if let Some(assignment) = member.assignment() {
+                                topics.extend(super::decode_member_assignment(assignment));
+                            }
+                        }
+                    }
+                    ConsumerGroupInfo {
+                        group_id: g.name().to_string(),
+                        state: g.state().to_string(),
+                        topics: topics.into_iter().collect(),
+                    }
+                })
+                .collect();
+
+            Ok(ClusterSnapshot {
+                harvested_at: chrono::Utc::now(),
+                topics,
+                consumer_groups,
+            })
+        }
+    }
+
+    impl ClusterAdmin for RdKafkaAdmin {
+        fn describe(&self) -> String {
+            format!("kafka://{}", self.bootstrap_servers)
+        }
+
+        fn snapshot(&self) -> LocalBoxFuture<'_, Result<ClusterSnapshot, APIError>> {
+            let config = self.config.clone();
+            let timeout = self.timeout;
+            async move {
+                // librdkafka calls block, keep them off the actix worker
+                actix_web::rt::task::spawn_blocking(move || RdKafkaAdmin::fetch(config, timeout))
+                    .await
+                    .map_err(|e| APIError::new(&format!("Kafka metadata task failed: {}", e)))?
+            }
+            .boxed_local()
+        }
+    }
+}
+
+/// Decode the topics of a consumer group member assignment (Kafka ConsumerProtocol):
+/// `version: i16, [topic: string, [partition: i32]], user_data: bytes`.
+/// Malformed input yields the topics read so far.
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+fn decode_member_assignment(data: &[u8]) -> Vec<String> {
+    fn read_i16(data: &[u8], pos: &mut usize) -> Option<i16> {
+        let bytes = data.get(*pos..*pos + 2)?;
+        *pos += 2;
+        Some(i16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+    fn read_i32(data: &[u8], pos: &mut usize) -> Option<i32> {
+        let bytes = data.get(*pos..*pos + 4)?;
+        *pos += 4;
+        Some(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    let mut topics = Vec::new();
+    let mut pos = 0;
+    let _version = read_i16(data, &mut pos);
+    let topic_count = read_i32(data, &mut pos).unwrap_or(0).max(0);
+    for _ in 0..topic_count {
+        let Some(len) = read_i16(data, &mut pos) else {
+            break;
+        };
+        let Some(name) = data.get(pos..pos + len.max(0) as usize) else {
+            break;
+        };
+        pos += len.max(0) as usize;
+        topics.push(String::from_utf8_lossy(name).to_string());
+        let partitions = read_i32(data, &mut pos).unwrap_or(0).max(0) as usize;
+        pos += partitions * 4;
+    }
+    topics
+}
+
+/// Select the cluster admin from its configured location: `file://snapshot.json` for a saved
+/// snapshot, otherwise a comma separated list of bootstrap servers.
+pub fn cluster_admin_from_config(
+    location: &str,
+    properties: &[(String, String)],
+) -> Result<Box<dyn ClusterAdmin>, APIError> {
+    if let Some(path) = location.strip_prefix("file://") {
+        return Ok(Box::new(SnapshotFileAdmin::new(path)));
+    }
+    let bootstrap_servers = location.strip_prefix("kafka://").unwrap_or(location);
+    #[cfg(feature = "kafka")]
+    {
+        Ok(Box::new(RdKafkaAdmin::new(bootstrap_servers, properties)))
+    }
+    #[cfg(not(feature = "kafka"))]
+    {
+        let _ = properties;
+        Err(APIError::new(&format!(
+            "Cannot connect to kafka cluster {}, the service was built without the kafka feature",
+            bootstrap_servers
+        )))
+    }
+}
+
+fn is_internal_topic(topic: &str) -> bool {
+    topic.starts_with("__")
+}
+
+/// Append rows for cluster topics and group assignments that the declared datasets don't
+/// have, so declared metadata (owners, descriptions, app names) wins where it exists.
+/// Every row gets a `Record_Source` column telling whether it was declared or harvested.
+pub fn merge_with_cluster(
+    ds_inventory: &DataFrame,
+    ds_consumer: &DataFrame,
+    snapshot: &ClusterSnapshot,
+) -> Result<(DataFrame, DataFrame), APIError> {
+    let declared_topics: BTreeSet<String> =
+        string_values(ds_inventory, COL_TOPIC_NAME_INVENTORY_FILE)?
+            .into_iter()
+            .flatten()
+            .collect();
+    let live_topics: Vec<String> = snapshot
+        .topics
+        .iter()
+        .filter(|t| !is_internal_topic(t) && !declared_topics.contains(*t))
+        .cloned()
+        .collect();
+    debug!("Topics only found in the cluster: {:?}", live_topics);
+
+    let inventory = append_rows(
+        ds_inventory,
+        &[
+            (COL_APP_OWNER_INVENTORY_FILE, vec![None; live_topics.len()]),
+            (
+                COL_TOPIC_NAME_INVENTORY_FILE,
+                live_topics.iter().map(|t| Some(t.clone())).collect(),
+            ),
+        ],
+    )?;
+
+    let groups = string_values(ds_consumer, COL_CONSUMER_GROUP_NAME_CONSUMER_FILE)?;
+    let topics = string_values(ds_consumer, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE)?;
+    let apps = string_values(ds_consumer, COL_CONSUMER_APP_NAME_CONSUMER_FILE)?;
+    let mut declared_bindings = BTreeSet::new();
+    let mut app_by_group: HashMap<String, String> = HashMap::new();
+    for ((group, topic), app) in groups.into_iter().zip(topics).zip(apps) {
+        if let Some(group) = group {
+            if let Some(app) = app {
+                app_by_group.entry(group.clone()).or_insert(app);
+            }
+            declared_bindings.insert((group, topic.unwrap_or_default()));
+        }
+    }
+
+    let mut new_apps = Vec::new();
+    let mut new_topics = Vec::new();
+    let mut new_groups = Vec::new();
+    for group in &snapshot.consumer_groups {
+        for topic in &group.topics {
+            if declared_bindings.contains(&(group.group_id.clone(), topic.clone())) {
+                continue;
+            }
+            // Without a declared app the group id is the best name for the consumer
+            let app = app_by_group
+                .get(&group.group_id)
+                .cloned()
+                .unwrap_or(group.group_id.clone());
+            new_apps.push(Some(app));
+            new_topics.push(Some(topic.clone()));
+            new_groups.push(Some(group.group_id.clone()));
+        }
+    }
+
+    let consumer = append_rows(
+        ds_consumer,
+        &[
+            (COL_CONSUMER_APP_NAME_CONSUMER_FILE, new_apps),
+            (COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE, new_topics),
+            (COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, new_groups),
+        ],
+    )?;
+    Ok((inventory, consumer))
+}
+
+/// Tag `ds` as declared and append harvested rows holding `values`, other columns left null.
+fn append_rows(
+    ds: &DataFrame,
+    values: &[(&str, Vec<Option<String>>)],
+) -> Result<DataFrame, APIError> {
+    let map_err = |e: PolarsError| APIError::new(&format!("Failed to merge cluster data: {}", e));
+    let mut declared = ds.clone();
+    declared
+        .with_column(Series::new(
+            COL_RECORD_SOURCE,
+            vec![RECORD_SOURCE_INVENTORY; ds.height()],
+        ))
+        .map_err(map_err)?;
+
+    let rows = values.first().map(|(_, v)| v.len()).unwrap_or(0);
+    let mut columns = Vec::new();
+    for field in ds.schema().iter_fields() {
+        let series = match values.iter().find(|(name, _)| *name == field.name.as_str()) {
+            Some((name, v)) => Series::new(name, v)
+                .cast(field.data_type())
+                .map_err(map_err)?,
+            None => Series::full_null(field.name.as_str(), rows, field.data_type()),
+        };
+        columns.push(series);
+    }
+    columns.push(Series::new(
+        COL_RECORD_SOURCE,
+        vec![RECORD_SOURCE_CLUSTER; rows],
+    ));
+    let harvested = DataFrame::new(columns).map_err(map_err)?;
+
+    declared.vstack(&harvested).map_err(map_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use futures::future::LocalBoxFuture;
+    use futures::FutureExt;
+    use polars::prelude::*;
+
+    use super::*;
+    use crate::data_service::get_app_list;
+
+    // Stands in for the admin API of a live cluster
+    struct MockAdmin {
+        snapshot: ClusterSnapshot,
+    }
+
+    impl ClusterAdmin for MockAdmin {
+        fn describe(&self) -> String {
+            "mock://cluster".to_string()
+        }
+
+        fn snapshot(&self) -> LocalBoxFuture<'_, Result<ClusterSnapshot, APIError>> {
+            let snapshot = self.snapshot.clone();
+            async move { Ok(snapshot) }.boxed_local()
+        }
+    }
+
+    fn mock_admin() -> MockAdmin {
+        MockAdmin {
+            snapshot: ClusterSnapshot {
+                harvested_at: Utc::now(),
+                topics: vec![
+                    "payments.txn".to_string(),
+                    "audit.log".to_string(),
+                    "__consumer_offsets".to_string(),
+                ],
+                consumer_groups: vec![
+                    ConsumerGroupInfo {
+                        group_id: "ledger-txn".to_string(),
+                        state: "Stable".to_string(),
+                        topics: vec!["payments.txn".to_string()],
+                    },
+                    ConsumerGroupInfo {
+                        group_id: "audit-sink".to_string(),
+                        state: "Stable".to_string(),
+                        topics: vec!["audit.log".to_string()],
+                    },
+                ],
+            },
+        }
+    }
+
+    // payments.txn is declared and live, payments.refund only declared
+    fn declared() -> (DataFrame, DataFrame) {
+        let inventory = df!(
+            COL_APP_OWNER_INVENTORY_FILE => ["payments", "payments"],
+            COL_TOPIC_NAME_INVENTORY_FILE => ["payments.txn", "payments.refund"],
+        )
+        .unwrap();
+        let consumer = df!(
+            COL_CONSUMER_APP_NAME_CONSUMER_FILE => ["ledger"],
+            COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE => ["payments.txn"],
+            COL_CONSUMER_GROUP_NAME_CONSUMER_FILE => ["ledger-txn"],
+        )
+        .unwrap();
+        (inventory, consumer)
+    }
+
+    // (owner, source) of every row of `topic`
+    fn topic_rows(inventory: &DataFrame, topic: &str) -> Vec<(Option<String>, String)> {
+        let owners = string_values(inventory, COL_APP_OWNER_INVENTORY_FILE).unwrap();
+        let topics = string_values(inventory, COL_TOPIC_NAME_INVENTORY_FILE).unwrap();
+        let sources = string_values(inventory, COL_RECORD_SOURCE).unwrap();
+        owners
+            .into_iter()
+            .zip(topics)
+            .zip(sources)
+            .filter(|((_, t), _)| t.as_deref() == Some(topic))
+            .map(|((owner, _), source)| (owner, source.unwrap()))
+            .collect()
+    }
+
+    async fn merged() -> (DataFrame, DataFrame) {
+        let (inventory, consumer) = declared();
+        let snapshot = mock_admin().snapshot().await.unwrap();
+        merge_with_cluster(&inventory, &consumer, &snapshot).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn topic_only_in_the_cluster_is_added_without_an_owner() {
+        let (inventory, consumer) = merged().await;
+        assert_eq!(
+            topic_rows(&inventory, "audit.log"),
+            [(None, RECORD_SOURCE_CLUSTER.to_string())]
+        );
+        assert!(topic_rows(&inventory, "__consumer_offsets").is_empty());
+        // unowned topics don't show up as an app
+        assert_eq!(get_app_list(&inventory).unwrap(), ["payments"]);
+
+        // the group id names the consumer of an undeclared group
+        let apps = string_values(&consumer, COL_CONSUMER_APP_NAME_CONSUMER_FILE).unwrap();
+        let topics = string_values(&consumer, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE).unwrap();
+        assert!(apps
+            .iter()
+            .zip(&topics)
+            .any(|(app, topic)| app.as_deref() == Some("audit-sink")
+                && topic.as_deref() == Some("audit.log")));
+    }
+
+    #[actix_web::test]
+    async fn topic_only_in_the_file_is_kept() {
+        let (inventory, _) = merged().await;
+        assert_eq!(
+            topic_rows(&inventory, "payments.refund"),
+            [(
+                Some("payments".to_string()),
+                RECORD_SOURCE_INVENTORY.to_string()
+            )]
+        );
+    }
+
+    #[actix_web::test]
+    async fn topic_in_both_keeps_the_declared_row_only() {
+        let (inventory, consumer) = merged().await;
+        assert_eq!(
+            topic_rows(&inventory, "payments.txn"),
+            [(
+                Some("payments".to_string()),
+                RECORD_SOURCE_INVENTORY.to_string()
+            )]
+        );
+        // the live assignment of the declared group isn't added again
+        assert_eq!(consumer.height(), 2);
+        let sources = string_values(&consumer, COL_RECORD_SOURCE).unwrap();
+        assert_eq!(
+            sources[0].as_deref(),
+            Some(RECORD_SOURCE_INVENTORY),
+            "declared binding first"
+        );
+    }
+}
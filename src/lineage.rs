@@ -0,0 +1,941 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::debug;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{consumer_bindings_from_frame, topics_from_frame};
+use crate::entities::{APIError, ErrorCode, LineageDirection};
+
+pub const LINEAGE_DEFAULT_DEPTH: usize = 3;
+pub const LINEAGE_MAX_DEPTH: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    App,
+    Topic,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    // app -> topic
+    Produces,
+    // topic -> app
+    Consumes,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LineageNode {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "kind")]
+    pub kind: NodeKind,
+    #[serde(rename = "name")]
+    pub name: String,
+    // app hops from the root, a topic has the hop of the apps it leads to
+    #[serde(rename = "depth")]
+    pub depth: usize,
+}
+
+/// Edges always point the way the messages flow, whatever the direction of the walk.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LineageEdge {
+    #[serde(rename = "source")]
+    pub source: String,
+    #[serde(rename = "target")]
+    pub target: String,
+    #[serde(rename = "kind")]
+    pub kind: EdgeKind,
+    // the groups the app consumes the topic with, empty for produces edges
+    #[serde(rename = "consumer_group_ids", skip_serializing_if = "Vec::is_empty")]
+    pub consumer_group_ids: Vec<String>,
+}
+
+/// The apps and topics within `depth` app hops of `root`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Lineage {
+    #[serde(rename = "root")]
+    pub root: String,
+    #[serde(rename = "direction")]
+    pub direction: LineageDirection,
+    #[serde(rename = "depth")]
+    pub depth: usize,
+    #[serde(rename = "nodes")]
+    pub nodes: Vec<LineageNode>,
+    #[serde(rename = "edges")]
+    pub edges: Vec<LineageEdge>,
+    // apps depending on each other in a loop, one sorted list per loop
+    #[serde(rename = "cycles")]
+    pub cycles: Vec<Vec<String>>,
+    // true if apps further than `depth` hops were left out
+    #[serde(rename = "truncated")]
+    pub truncated: bool,
+}
+
+/// An app depending on the changed topic or app, and the shortest path reaching it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AffectedApp {
+    #[serde(rename = "app_name")]
+    pub app_name: String,
+    #[serde(rename = "distance")]
+    pub distance: usize,
+    // node ids from the changed topic or app to this one
+    #[serde(rename = "path")]
+    pub path: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AffectedConsumerGroup {
+    #[serde(rename = "consumer_group_id")]
+    pub consumer_group_id: String,
+    #[serde(rename = "app_name")]
+    pub app_name: String,
+    #[serde(rename = "topic_name")]
+    pub topic_name: String,
+    #[serde(rename = "distance")]
+    pub distance: usize,
+    #[serde(rename = "path")]
+    pub path: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImpactSummary {
+    // affected apps, an app is named after the project owning it
+    #[serde(rename = "apps")]
+    pub apps: usize,
+    #[serde(rename = "consumer_groups")]
+    pub consumer_groups: usize,
+    #[serde(rename = "topics")]
+    pub topics: usize,
+    #[serde(rename = "max_distance")]
+    pub max_distance: usize,
+}
+
+/// Everything downstream of a topic or app, nearest first.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImpactReport {
+    #[serde(rename = "root")]
+    pub root: String,
+    #[serde(rename = "max_depth")]
+    pub max_depth: usize,
+    #[serde(rename = "affected_apps")]
+    pub affected_apps: Vec<AffectedApp>,
+    #[serde(rename = "affected_consumer_groups")]
+    pub affected_consumer_groups: Vec<AffectedConsumerGroup>,
+    #[serde(rename = "summary")]
+    pub summary: ImpactSummary,
+    // true if apps further than `max_depth` hops were left out
+    #[serde(rename = "truncated")]
+    pub truncated: bool,
+}
+
+/// Producer -> topic -> consumer links of the inventory and consumer datasets.
+#[derive(Default)]
+pub struct LineageGraph {
+    // app -> topics it produces
+    produces: BTreeMap<String, BTreeSet<String>>,
+    // topic -> apps producing it
+    producers: BTreeMap<String, BTreeSet<String>>,
+    // app -> topic -> consumer groups
+    consumes: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+    // topic -> app -> consumer groups
+    consumers: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+}
+
+// Nodes and edges collected by the walks from the root
+#[derive(Default)]
+struct Subgraph {
+    nodes: BTreeMap<String, LineageNode>,
+    edges: BTreeMap<(String, String), LineageEdge>,
+    truncated: bool,
+}
+
+impl LineageGraph {
+    pub fn build(ds_inventory: &DataFrame, ds_consumer: &DataFrame) -> Result<Self, APIError> {
+        let mut graph = LineageGraph::default();
+        // topics without an owner are only known from the cluster
+        for topic in topics_from_frame(ds_inventory)? {
+            if topic.project.is_empty() {
+                continue;
+            }
+            graph
+                .produces
+                .entry(topic.project.clone())
+                .or_default()
+                .insert(topic.name.clone());
+            graph
+                .producers
+                .entry(topic.name)
+                .or_default()
+                .insert(topic.project);
+        }
+        for binding in consumer_bindings_from_frame(ds_consumer)? {
+            if binding.consumer_app.is_empty() {
+                continue;
+            }
+            graph
+                .consumes
+                .entry(binding.consumer_app.clone())
+                .or_default()
+                .entry(binding.topic_name.clone())
+                .or_default()
+                .insert(binding.consumer_group.clone());
+            graph
+                .consumers
+                .entry(binding.topic_name)
+                .or_default()
+                .entry(binding.consumer_app)
+                .or_default()
+                .insert(binding.consumer_group);
+        }
+        debug!(
+            "Lineage graph: {} producing apps, {} consuming apps",
+            graph.produces.len(),
+            graph.consumes.len()
+        );
+        Ok(graph)
+    }
+
+    pub fn contains_app(&self, app: &str) -> bool {
+        self.produces.contains_key(app) || self.consumes.contains_key(app)
+    }
+
+    /// Walk from `root` up to the producers it depends on, down to the consumers depending
+    /// on it, or both, at most `depth` app hops away.
+    pub fn lineage(
+        &self,
+        root: &str,
+        direction: LineageDirection,
+        depth: usize,
+    ) -> Result<Lineage, APIError> {
+        check_depth("depth", depth)?;
+        if !self.contains_app(root) {
+            return Err(APIError::with_code(
+                ErrorCode::NotFound,
+                &format!("No app {}", root),
+            ));
+        }
+
+        let mut subgraph = Subgraph::default();
+        subgraph.add_node(NodeKind::App, root, 0);
+        if direction != LineageDirection::Upstream {
+            self.walk(root, depth, false, &mut subgraph);
+        }
+        if direction != LineageDirection::Downstream {
+            self.walk(root, depth, true, &mut subgraph);
+        }
+
+        let cycles = app_cycles(&subgraph.edges);
+        Ok(Lineage {
+            root: root.to_string(),
+            direction,
+            depth,
+            nodes: subgraph.nodes.into_values().collect(),
+            edges: subgraph.edges.into_values().collect(),
+            cycles,
+            truncated: subgraph.truncated,
+        })
+    }
+
+    pub fn contains_topic(&self, topic: &str) -> bool {
+        self.producers.contains_key(topic) || self.consumers.contains_key(topic)
+    }
+
+    /// The apps and consumer groups reading `root`, a topic or the topics of an app, directly
+    /// or through the topics of other affected apps, at most `max_depth` app hops away.
+    pub fn impact(
+        &self,
+        kind: NodeKind,
+        root: &str,
+        max_depth: usize,
+    ) -> Result<ImpactReport, APIError> {
+        check_depth("max_depth", max_depth)?;
+        // topics to follow at the next hop, with the path reaching them
+        let mut topics: Vec<(&str, Vec<String>)> = match kind {
+            NodeKind::Topic if self.contains_topic(root) => {
+                vec![(root, vec![node_id(NodeKind::Topic, root)])]
+            }
+            NodeKind::App if self.contains_app(root) => {
+                self.produced_topics(root, &[node_id(NodeKind::App, root)])
+            }
+            NodeKind::Topic => {
+                return Err(APIError::with_code(
+                    ErrorCode::NotFound,
+                    &format!("No topic {}", root),
+                ))
+            }
+            NodeKind::App => {
+                return Err(APIError::with_code(
+                    ErrorCode::NotFound,
+                    &format!("No app {}", root),
+                ))
+            }
+        };
+
+        // breadth first, so the first path reaching an app or group is a shortest one
+        let mut visited: BTreeSet<&str> = BTreeSet::new();
+        if kind == NodeKind::App {
+            visited.insert(root);
+        }
+        let mut affected_topics: BTreeSet<&str> = BTreeSet::new();
+        let mut affected_apps = Vec::new();
+        let mut affected_consumer_groups = Vec::new();
+        let mut seen_groups: BTreeSet<(&str, &str)> = BTreeSet::new();
+        for distance in 1..=max_depth {
+            let mut next = Vec::new();
+            for (topic, path) in &topics {
+                affected_topics.insert(topic);
+                for (app, group_ids) in self.consumers.get(*topic).into_iter().flatten() {
+                    let mut app_path = path.clone();
+                    app_path.push(node_id(NodeKind::App, app));
+                    for group in group_ids.iter().filter(|g| !g.is_empty()) {
+                        if seen_groups.insert((group, topic)) {
+                            affected_consumer_groups.push(AffectedConsumerGroup {
+                                consumer_group_id: group.clone(),
+                                app_name: app.clone(),
+                                topic_name: topic.to_string(),
+                                distance,
+                                path: app_path.clone(),
+                            });
+                        }
+                    }
+                    if visited.insert(app) {
+                        next.extend(self.produced_topics(app, &app_path));
+                        affected_apps.push(AffectedApp {
+                            app_name: app.clone(),
+                            distance,
+                            path: app_path,
+                        });
+                    }
+                }
+            }
+            topics = next;
+        }
+        let truncated = topics.iter().any(|(topic, _)| {
+            self.consumers
+                .get(*topic)
+                .is_some_and(|apps| apps.keys().any(|app| !visited.contains(app.as_str())))
+        });
+
+        let summary = ImpactSummary {
+            apps: affected_apps.len(),
+            consumer_groups: affected_consumer_groups
+                .iter()
+                .map(|g| g.consumer_group_id.as_str())
+                .collect::<BTreeSet<&str>>()
+                .len(),
+            topics: affected_topics.len(),
+            max_distance: affected_apps.iter().map(|a| a.distance).max().unwrap_or(0),
+        };
+        Ok(ImpactReport {
+            root: node_id(kind, root),
+            max_depth,
+            affected_apps,
+            affected_consumer_groups,
+            summary,
+            truncated,
+        })
+    }
+
+    // The topics `app` produces, each with `path` extended by the topic.
+    fn produced_topics<'a>(&'a self, app: &str, path: &[String]) -> Vec<(&'a str, Vec<String>)> {
+        self.produces
+            .get(app)
+            .into_iter()
+            .flatten()
+            .map(|topic| {
+                let mut topic_path = path.to_vec();
+                topic_path.push(node_id(NodeKind::Topic, topic));
+                (topic.as_str(), topic_path)
+            })
+            .collect()
+    }
+
+    // Breadth first, so every app is reached at its smallest hop count and visited once,
+    // which also stops the walk going round a cycle.
+    fn walk(&self, root: &str, depth: usize, upstream: bool, subgraph: &mut Subgraph) {
+        let mut visited: BTreeSet<&str> = BTreeSet::from([root]);
+        let mut frontier: Vec<&str> = vec![root];
+        for hop in 1..=depth {
+            let mut next = Vec::new();
+            for app in frontier {
+                for neighbour in self.links(app, upstream, subgraph, hop) {
+                    if visited.insert(neighbour) {
+                        next.push(neighbour);
+                    }
+                    subgraph.add_node(NodeKind::App, neighbour, hop);
+                }
+            }
+            frontier = next;
+        }
+        // apps one hop further that were left out
+        subgraph.truncated |= frontier.iter().any(|app| {
+            self.neighbours(app, upstream)
+                .any(|(neighbour, _, _)| !visited.contains(neighbour))
+        });
+    }
+
+    // Adds the topics of `app` and the edges to its neighbours, returns the neighbours.
+    fn links<'a>(
+        &'a self,
+        app: &str,
+        upstream: bool,
+        subgraph: &mut Subgraph,
+        hop: usize,
+    ) -> Vec<&'a str> {
+        // topics nobody consumes, or nobody produces, are still part of the lineage
+        if upstream {
+            for (topic, groups) in self.consumes.get(app).into_iter().flatten() {
+                subgraph.add_node(NodeKind::Topic, topic, hop);
+                subgraph.add_consumes(topic, app, groups);
+            }
+        } else {
+            for topic in self.produces.get(app).into_iter().flatten() {
+                subgraph.add_node(NodeKind::Topic, topic, hop);
+                subgraph.add_produces(app, topic);
+            }
+        }
+
+        let mut neighbours = Vec::new();
+        for (neighbour, topic, groups) in self.neighbours(app, upstream) {
+            if upstream {
+                subgraph.add_produces(neighbour, topic);
+            } else {
+                subgraph.add_consumes(topic, neighbour, groups);
+            }
+            neighbours.push(neighbour);
+        }
+        neighbours
+    }
+
+    // Producers of the topics `app` consumes, or consumers of the topics it produces, with
+    // the topic and the consumer groups of the link.
+    fn neighbours<'a>(
+        &'a self,
+        app: &str,
+        upstream: bool,
+    ) -> Box<dyn Iterator<Item = (&'a str, &'a str, &'a BTreeSet<String>)> + 'a> {
+        if upstream {
+            let Some(topics) = self.consumes.get(app) else {
+                return Box::new(std::iter::empty());
+            };
+            Box::new(topics.iter().flat_map(move |(topic, groups)| {
+                self.producers
+                    .get(topic)
+                    .into_iter()
+                    .flatten()
+                    .map(move |producer| (producer.as_str(), topic.as_str(), groups))
+            }))
+        } else {
+            let Some(topics) = self.produces.get(app) else {
+                return Box::new(std::iter::empty());
+            };
+            Box::new(topics.iter().flat_map(move |topic| {
+                self.consumers
+                    .get(topic)
+                    .into_iter()
+                    .flatten()
+                    .map(move |(consumer, groups)| (consumer.as_str(), topic.as_str(), groups))
+            }))
+        }
+    }
+}
+
+impl Subgraph {
+    fn add_node(&mut self, kind: NodeKind, name: &str, depth: usize) {
+        let node = self
+            .nodes
+            .entry(node_id(kind, name))
+            .or_insert_with(|| LineageNode {
+                id: node_id(kind, name),
+                kind,
+                name: name.to_string(),
+                depth,
+            });
+        node.depth = node.depth.min(depth);
+    }
+
+    fn add_produces(&mut self, app: &str, topic: &str) {
+        self.add_edge(
+            node_id(NodeKind::App, app),
+            node_id(NodeKind::Topic, topic),
+            EdgeKind::Produces,
+            &BTreeSet::new(),
+        );
+    }
+
+    fn add_consumes(&mut self, topic: &str, app: &str, groups: &BTreeSet<String>) {
+        self.add_edge(
+            node_id(NodeKind::Topic, topic),
+            node_id(NodeKind::App, app),
+            EdgeKind::Consumes,
+            groups,
+        );
+    }
+
+    fn add_edge(
+        &mut self,
+        source: String,
+        target: String,
+        kind: EdgeKind,
+        groups: &BTreeSet<String>,
+    ) {
+        self.edges
+            .entry((source.clone(), target.clone()))
+            .or_insert_with(|| LineageEdge {
+                source,
+                target,
+                kind,
+                consumer_group_ids: groups.iter().filter(|g| !g.is_empty()).cloned().collect(),
+            });
+    }
+}
+
+fn check_depth(name: &str, depth: usize) -> Result<(), APIError> {
+    if depth == 0 || depth > LINEAGE_MAX_DEPTH {
+        return Err(APIError::with_code(
+            ErrorCode::InvalidRequest,
+            &format!("{} must be between 1 and {}", name, LINEAGE_MAX_DEPTH),
+        ));
+    }
+    Ok(())
+}
+
+pub fn node_id(kind: NodeKind, name: &str) -> String {
+    match kind {
+        NodeKind::App => format!("app:{}", name),
+        NodeKind::Topic => format!("topic:{}", name),
+    }
+}
+
+// Strongly connected components of the app -> topic -> app links with more than one app,
+// or an app consuming its own topic.
+fn app_cycles(edges: &BTreeMap<(String, String), LineageEdge>) -> Vec<Vec<String>> {
+    let mut topic_consumers: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for edge in edges.values().filter(|e| e.kind == EdgeKind::Consumes) {
+        topic_consumers
+            .entry(edge.source.as_str())
+            .or_default()
+            .push(edge.target.as_str());
+    }
+    let mut apps: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for edge in edges.values().filter(|e| e.kind == EdgeKind::Produces) {
+        let next = topic_consumers
+            .get(edge.target.as_str())
+            .cloned()
+            .unwrap_or_default();
+        apps.entry(edge.source.as_str()).or_default().extend(next);
+    }
+
+    let mut tarjan = Tarjan {
+        graph: &apps,
+        index: BTreeMap::new(),
+        low_link: BTreeMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        components: Vec::new(),
+    };
+    for app in apps.keys() {
+        if !tarjan.index.contains_key(app) {
+            tarjan.visit(app);
+        }
+    }
+
+    let mut cycles: Vec<Vec<String>> = tarjan
+        .components
+        .into_iter()
+        .filter(|component| {
+            component.len() > 1
+                || apps
+                    .get(component[0])
+                    .is_some_and(|next| next.contains(&component[0]))
+        })
+        .map(|component| {
+            let mut names: Vec<String> = component
+                .into_iter()
+                .map(|id| id.trim_start_matches("app:").to_string())
+                .collect();
+            names.sort();
+            names
+        })
+        .collect();
+    cycles.sort();
+    cycles
+}
+
+struct Tarjan<'a> {
+    graph: &'a BTreeMap<&'a str, Vec<&'a str>>,
+    index: BTreeMap<&'a str, usize>,
+    low_link: BTreeMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: BTreeSet<&'a str>,
+    components: Vec<Vec<&'a str>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, node: &'a str) {
+        let index = self.index.len();
+        self.index.insert(node, index);
+        self.low_link.insert(node, index);
+        self.stack.push(node);
+        self.on_stack.insert(node);
+
+        let graph = self.graph;
+        for &next in graph.get(node).into_iter().flatten() {
+            if !self.index.contains_key(next) {
+                self.visit(next);
+                let low = self.low_link[node].min(self.low_link[next]);
+                self.low_link.insert(node, low);
+            } else if self.on_stack.contains(next) {
+                let low = self.low_link[node].min(self.index[next]);
+                self.low_link.insert(node, low);
+            }
+        }
+
+        if self.low_link[node] == index {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_service::{
+        COL_APP_OWNER_INVENTORY_FILE, COL_CONSUMER_APP_NAME_CONSUMER_FILE,
+        COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE,
+        COL_TOPIC_NAME_INVENTORY_FILE,
+    };
+
+    // `produces` are (app, topic), `consumes` are (app, topic, group)
+    fn graph(produces: &[(&str, &str)], consumes: &[(&str, &str, &str)]) -> LineageGraph {
+        let ds_inventory = df!(
+            COL_APP_OWNER_INVENTORY_FILE => produces.iter().map(|p| p.0).collect::<Vec<_>>(),
+            COL_TOPIC_NAME_INVENTORY_FILE => produces.iter().map(|p| p.1).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let ds_consumer = df!(
+            COL_CONSUMER_APP_NAME_CONSUMER_FILE => consumes.iter().map(|c| c.0).collect::<Vec<_>>(),
+            COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE => consumes.iter().map(|c| c.1).collect::<Vec<_>>(),
+            COL_CONSUMER_GROUP_NAME_CONSUMER_FILE => consumes.iter().map(|c| c.2).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        LineageGraph::build(&ds_inventory, &ds_consumer).unwrap()
+    }
+
+    // a -> t1 -> b -> t2 -> c -> t3 -> d
+    fn chain() -> LineageGraph {
+        graph(
+            &[("a", "t1"), ("b", "t2"), ("c", "t3")],
+            &[("b", "t1", "g1"), ("c", "t2", "g2"), ("d", "t3", "g3")],
+        )
+    }
+
+    fn nodes(lineage: &Lineage) -> Vec<(&str, usize)> {
+        lineage
+            .nodes
+            .iter()
+            .map(|n| (n.id.as_str(), n.depth))
+            .collect()
+    }
+
+    fn edges(lineage: &Lineage) -> Vec<(&str, &str, EdgeKind)> {
+        lineage
+            .edges
+            .iter()
+            .map(|e| (e.source.as_str(), e.target.as_str(), e.kind))
+            .collect()
+    }
+
+    #[test]
+    fn downstream_follows_the_consumers() {
+        let lineage = chain()
+            .lineage("b", LineageDirection::Downstream, 3)
+            .unwrap();
+        assert_eq!(
+            nodes(&lineage),
+            [
+                ("app:b", 0),
+                ("app:c", 1),
+                ("app:d", 2),
+                ("topic:t2", 1),
+                ("topic:t3", 2)
+            ]
+        );
+        assert_eq!(
+            edges(&lineage),
+            [
+                ("app:b", "topic:t2", EdgeKind::Produces),
+                ("app:c", "topic:t3", EdgeKind::Produces),
+                ("topic:t2", "app:c", EdgeKind::Consumes),
+                ("topic:t3", "app:d", EdgeKind::Consumes),
+            ]
+        );
+        assert_eq!(lineage.edges[2].consumer_group_ids, ["g2"]);
+        assert!(lineage.edges[0].consumer_group_ids.is_empty());
+        assert!(!lineage.truncated);
+        assert!(lineage.cycles.is_empty());
+    }
+
+    #[test]
+    fn upstream_follows_the_producers() {
+        let lineage = chain().lineage("c", LineageDirection::Upstream, 3).unwrap();
+        assert_eq!(
+            nodes(&lineage),
+            [
+                ("app:a", 2),
+                ("app:b", 1),
+                ("app:c", 0),
+                ("topic:t1", 2),
+                ("topic:t2", 1)
+            ]
+        );
+        // edges still point the way the messages flow
+        assert_eq!(
+            edges(&lineage),
+            [
+                ("app:a", "topic:t1", EdgeKind::Produces),
+                ("app:b", "topic:t2", EdgeKind::Produces),
+                ("topic:t1", "app:b", EdgeKind::Consumes),
+                ("topic:t2", "app:c", EdgeKind::Consumes),
+            ]
+        );
+        assert!(!lineage.truncated);
+    }
+
+    #[test]
+    fn both_directions_and_the_depth_cut_off() {
+        let lineage = chain().lineage("b", LineageDirection::Both, 1).unwrap();
+        assert_eq!(
+            nodes(&lineage),
+            [
+                ("app:a", 1),
+                ("app:b", 0),
+                ("app:c", 1),
+                ("topic:t1", 1),
+                ("topic:t2", 1)
+            ]
+        );
+        // d is one hop past c
+        assert!(lineage.truncated);
+
+        let graph = chain();
+        let lineage = graph.lineage("a", LineageDirection::Downstream, 2).unwrap();
+        assert!(nodes(&lineage).iter().all(|(id, _)| *id != "app:d"));
+        assert!(lineage.truncated);
+        let lineage = graph.lineage("a", LineageDirection::Downstream, 3).unwrap();
+        assert!(nodes(&lineage).contains(&("app:d", 3)));
+        assert!(!lineage.truncated);
+        // nothing is upstream of a
+        let lineage = graph.lineage("a", LineageDirection::Upstream, 1).unwrap();
+        assert_eq!(nodes(&lineage), [("app:a", 0)]);
+        assert!(!lineage.truncated);
+    }
+
+    #[test]
+    fn cycles_of_apps() {
+        // x and y consume each other's topics, s consumes its own, z only reads from x
+        let graph = graph(
+            &[("x", "tx"), ("y", "ty"), ("s", "ts")],
+            &[
+                ("y", "tx", "gy"),
+                ("x", "ty", "gx"),
+                ("z", "tx", "gz"),
+                ("s", "ts", "gs"),
+            ],
+        );
+        let lineage = graph.lineage("x", LineageDirection::Both, 3).unwrap();
+        assert_eq!(lineage.cycles, [["x", "y"]]);
+        assert!(!lineage.truncated);
+
+        let lineage = graph.lineage("s", LineageDirection::Downstream, 1).unwrap();
+        assert_eq!(lineage.cycles, [["s"]]);
+        assert_eq!(nodes(&lineage), [("app:s", 0), ("topic:ts", 1)]);
+    }
+
+    #[test]
+    fn unknown_app_and_bad_depth_are_rejected() {
+        let graph = chain();
+        let e = graph
+            .lineage("nobody", LineageDirection::Both, 3)
+            .err()
+            .unwrap();
+        assert_eq!(e.code(), ErrorCode::NotFound);
+        for depth in [0, LINEAGE_MAX_DEPTH + 1] {
+            let e = graph
+                .lineage("a", LineageDirection::Both, depth)
+                .err()
+                .unwrap();
+            assert_eq!(e.code(), ErrorCode::InvalidRequest);
+        }
+        assert!(graph
+            .lineage("a", LineageDirection::Both, LINEAGE_MAX_DEPTH)
+            .is_ok());
+    }
+
+    fn affected_apps(report: &ImpactReport) -> Vec<(&str, usize, Vec<&str>)> {
+        report
+            .affected_apps
+            .iter()
+            .map(|a| {
+                let path = a.path.iter().map(|p| p.as_str()).collect();
+                (a.app_name.as_str(), a.distance, path)
+            })
+            .collect()
+    }
+
+    fn affected_groups(report: &ImpactReport) -> Vec<(&str, &str, &str, usize)> {
+        report
+            .affected_consumer_groups
+            .iter()
+            .map(|g| {
+                (
+                    g.consumer_group_id.as_str(),
+                    g.app_name.as_str(),
+                    g.topic_name.as_str(),
+                    g.distance,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn impact_of_a_topic() {
+        let graph = chain();
+        let report = graph.impact(NodeKind::Topic, "t1", 3).unwrap();
+        assert_eq!(report.root, "topic:t1");
+        assert_eq!(
+            affected_apps(&report),
+            [
+                ("b", 1, vec!["topic:t1", "app:b"]),
+                ("c", 2, vec!["topic:t1", "app:b", "topic:t2", "app:c"]),
+                (
+                    "d",
+                    3,
+                    vec!["topic:t1", "app:b", "topic:t2", "app:c", "topic:t3", "app:d"]
+                ),
+            ]
+        );
+        assert_eq!(
+            affected_groups(&report),
+            [
+                ("g1", "b", "t1", 1),
+                ("g2", "c", "t2", 2),
+                ("g3", "d", "t3", 3)
+            ]
+        );
+        assert_eq!(
+            report.affected_consumer_groups[1].path,
+            report.affected_apps[1].path
+        );
+        assert_eq!(report.summary.apps, 3);
+        assert_eq!(report.summary.consumer_groups, 3);
+        assert_eq!(report.summary.topics, 3);
+        assert_eq!(report.summary.max_distance, 3);
+        assert!(!report.truncated);
+
+        let report = graph.impact(NodeKind::Topic, "t1", 2).unwrap();
+        assert_eq!(report.affected_apps.len(), 2);
+        assert_eq!(report.summary.topics, 2);
+        assert_eq!(report.summary.max_distance, 2);
+        assert!(report.truncated);
+    }
+
+    #[test]
+    fn impact_of_an_app() {
+        let report = chain().impact(NodeKind::App, "a", 2).unwrap();
+        assert_eq!(report.root, "app:a");
+        assert_eq!(
+            affected_apps(&report),
+            [
+                ("b", 1, vec!["app:a", "topic:t1", "app:b"]),
+                (
+                    "c",
+                    2,
+                    vec!["app:a", "topic:t1", "app:b", "topic:t2", "app:c"]
+                ),
+            ]
+        );
+        assert_eq!(report.summary.topics, 2);
+        assert!(report.truncated);
+
+        // nothing reads d
+        let report = chain().impact(NodeKind::App, "d", 3).unwrap();
+        assert!(report.affected_apps.is_empty());
+        assert_eq!(report.summary.max_distance, 0);
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn impact_takes_the_shortest_path() {
+        // a reaches e through b in two hops and directly through t2 in one,
+        // and f through b and c, both two hops, on the same topic t5
+        let graph = graph(
+            &[
+                ("a", "t1"),
+                ("a", "t2"),
+                ("b", "t3"),
+                ("b", "t5"),
+                ("c", "t5"),
+            ],
+            &[
+                ("b", "t1", "gb"),
+                ("c", "t2", "gc"),
+                ("e", "t3", "ge"),
+                ("e", "t2", "ge"),
+                ("f", "t5", "gf"),
+            ],
+        );
+        let report = graph.impact(NodeKind::App, "a", 3).unwrap();
+        assert_eq!(
+            affected_apps(&report),
+            [
+                ("b", 1, vec!["app:a", "topic:t1", "app:b"]),
+                ("c", 1, vec!["app:a", "topic:t2", "app:c"]),
+                ("e", 1, vec!["app:a", "topic:t2", "app:e"]),
+                (
+                    "f",
+                    2,
+                    vec!["app:a", "topic:t1", "app:b", "topic:t5", "app:f"]
+                ),
+            ]
+        );
+        // ge is reported per topic, gf once although t5 is reached twice
+        assert_eq!(
+            affected_groups(&report),
+            [
+                ("gb", "b", "t1", 1),
+                ("gc", "c", "t2", 1),
+                ("ge", "e", "t2", 1),
+                ("ge", "e", "t3", 2),
+                ("gf", "f", "t5", 2),
+            ]
+        );
+        assert_eq!(report.summary.apps, 4);
+        assert_eq!(report.summary.consumer_groups, 4);
+        assert_eq!(report.summary.topics, 4);
+        assert_eq!(report.summary.max_distance, 2);
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn impact_of_unknown_roots_and_bad_depths() {
+        let graph = chain();
+        let e = graph.impact(NodeKind::Topic, "t9", 3).err().unwrap();
+        assert_eq!(e.code(), ErrorCode::NotFound);
+        let e = graph.impact(NodeKind::App, "nobody", 3).err().unwrap();
+        assert_eq!(e.code(), ErrorCode::NotFound);
+        let e = graph.impact(NodeKind::Topic, "t1", 0).err().unwrap();
+        assert_eq!(e.code(), ErrorCode::InvalidRequest);
+    }
+}
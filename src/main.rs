@@ -1,4 +1,5 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use actix_cors::Cors;
 use actix_files as fs;
@@ -12,38 +13,46 @@ use actix_web::http::header;
 use actix_web::middleware::{DefaultHeaders, Logger};
 use actix_web::web::Data;
 use actix_web::{middleware, web, App};
-use log::{debug, error, info};
+use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
-use crate::data_service::read_csv;
-use crate::data_utils::fetch_dataset_az_blob;
+use crate::authorization::{RequireRole, Role};
+use crate::entities::{APIError, ErrorCode, MQTopicDescription};
 use crate::entities_ai::AISearchIndex;
 
 use async_openai::config::AzureConfig;
 use std::fs as file_system;
 
+mod api_keys;
 mod apis;
+mod auth_provider;
+mod authorization;
 mod azure_ai_apis;
+mod cli;
+mod data_format;
+mod data_reload;
 mod data_service;
+mod data_source;
 mod data_state;
 mod data_utils;
+mod dataset_schema;
+mod domain;
+mod drift_report;
 mod entities;
 mod entities_ai;
 mod export;
+mod health_report;
 mod jwt_middleware;
+mod kafka_cluster;
+mod lineage;
+mod oidc;
+mod query_language;
+mod request_id;
+mod signing_keys;
+mod text_index;
+mod token_store;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct MQTopicDescription {
-    #[serde(rename = "business_module")]
-    business_module: String,
-    #[serde(rename = "topic_name")]
-    topic_name: String,
-    #[serde(rename = "publisher")]
-    publisher: String,
-    #[serde(rename = "remark")]
-    remark: String,
-}
 #[derive(Debug, Serialize, Deserialize)]
 struct MQDataDescription {
     #[serde(rename = "mq_data_background")]
@@ -58,7 +67,7 @@ struct MQDataDescription {
 
 fn is_allowed_origin(origin: &str) -> bool {
     // List of allowed origins
-    let allowed_origins = vec![
+    let allowed_origins = [
         "http://localhost:8888",
         "https://kafka-repo-dev001.azurewebsites.net",
     ];
@@ -66,7 +75,8 @@ fn is_allowed_origin(origin: &str) -> bool {
     allowed_origins.contains(&origin)
 }
 
-fn load_mq_knowledge(file_path: &str) -> String {
+// The knowledge text for the AI search, and the MQ topics it describes
+fn load_mq_knowledge(file_path: &str) -> (String, Vec<MQTopicDescription>) {
     let file_content = file_system::read_to_string(file_path).expect("Failed to read JSON file");
     let parsed_json: MQDataDescription =
         serde_json::from_str(&file_content).expect("Failed to parse JSON");
@@ -76,42 +86,83 @@ fn load_mq_knowledge(file_path: &str) -> String {
     let mut knowledge = String::new();
     knowledge.push_str("Here is the knowledge about Message sync MQ Pub/Sub :\n");
     knowledge.push_str(&parsed_json.mq_descriptions);
-    knowledge.push_str("\n");
+    knowledge.push('\n');
     knowledge.push_str("Here is the knowledge about Message sync MQ Pub/Sub Current State :\n");
     knowledge.push_str(&parsed_json.mq_data_current_state);
-    knowledge.push_str("\n");
+    knowledge.push('\n');
     knowledge.push_str("Here is the knowledge about Message sync MQ Pub/Sub Technology :\n");
     knowledge.push_str(&parsed_json.mq_technology);
-    knowledge.push_str("\n");
+    knowledge.push('\n');
     knowledge.push_str("Here is the knowledge about Message sync MQ Pub/Sub Topics :\n");
-    for topic in parsed_json.mq_pub_sub_topics {
+    for topic in &parsed_json.mq_pub_sub_topics {
         knowledge.push_str("Business Module: ");
         knowledge.push_str(&topic.business_module);
-        knowledge.push_str("\n");
+        knowledge.push('\n');
         knowledge.push_str("Topic Name or Topic String: ");
         knowledge.push_str(&topic.topic_name);
-        knowledge.push_str("\n");
+        knowledge.push('\n');
         knowledge.push_str("Publisher: ");
         knowledge.push_str(&topic.publisher);
-        knowledge.push_str("\n");
+        knowledge.push('\n');
         knowledge.push_str("Remark: ");
         knowledge.push_str(&topic.remark);
-        knowledge.push_str("\n");
+        knowledge.push('\n');
     }
-    knowledge.push_str("\n");
-    knowledge
+    knowledge.push('\n');
+    (knowledge, parsed_json.mq_pub_sub_topics)
 }
 
 fn create_openai(open_ai_url: &str, open_ai_key: &str) -> AzureConfig {
     debug!("open_ai_url: {}", open_ai_url);
 
-    let azure_config = AzureConfig::default()
+    
+
+    AzureConfig::default()
         .with_api_base(open_ai_url)
         .with_api_key(open_ai_key)
         .with_api_version("2023-03-15-preview")
-        .with_deployment_id("gpt-4");
+        .with_deployment_id("gpt-4")
+}
 
-    azure_config
+// Routes under /api/v1, all of them need the viewer role checked in JwtMiddleware
+fn api_v1_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/apps", web::get().to(apis::get_apps))
+        .route("/apps/{appName}", web::get().to(apis::get_app_detail))
+        .route("/apps/{appName}/topics", web::get().to(apis::get_topics))
+        .route("/topics/{name}", web::get().to(apis::get_topic_detail))
+        .route("/lineage/{appName}", web::get().to(apis::get_lineage))
+        .route("/impact", web::post().to(apis::post_impact))
+        .route("/consumers", web::get().to(apis::get_consumers))
+        .route("/search", web::post().to(apis::post_search_kafka))
+        // Azure AI Search and OpenAI calls cost money per request
+        .service(
+            web::resource("/ai_search")
+                .wrap(RequireRole::new(Role::Editor))
+                .route(web::post().to(apis::post_ai_search)),
+        )
+        .route(
+            "/render",
+            web::post().to(apis::post_topic_kafka_relation_render),
+        )
+        .service(
+            web::scope("/admin")
+                .wrap(RequireRole::new(Role::Admin))
+                .route("/reload", web::get().to(apis::get_reload_status))
+                .route("/reload", web::post().to(apis::post_reload))
+                .route("/tokens/revoke", web::post().to(apis::post_revoke_tokens))
+                .route("/api-keys", web::get().to(apis::get_api_keys))
+                .route("/api-keys", web::post().to(apis::post_api_key))
+                .route(
+                    "/api-keys/{id}/revoke",
+                    web::post().to(apis::post_revoke_api_key),
+                ),
+        )
+        .route("/reports/drift", web::get().to(apis::get_drift_report))
+        .route("/reports/health", web::get().to(apis::get_health_report))
+        .route(
+            "/reports/health.csv",
+            web::get().to(apis::get_health_report_csv),
+        );
 }
 
 #[actix_web::main]
@@ -119,22 +170,25 @@ async fn main() -> std::io::Result<()> {
     pretty_env_logger::init();
     dotenv::dotenv().ok();
 
-    // Read environment variables
-    let kafka_inventory_file =
-        std::env::var("KAFKA_INVENTORY_FILE").expect("KAFKA_INVENTORY_FILE must be set");
-    let kafka_consumer_file =
-        std::env::var("KAFKA_CONSUMER_FILE").expect("KAFKA_CONSUMER must be set");
-    // User Authentication
-    let user_authentication_file =
-        std::env::var("USER_AUTHENTICATION_FILE").expect("USER_AUTHENTICATION_FILE must be set");
-    // Azure Blob Storage for Datasets Kafka Inventory and Kafka Consumer and User Authentication
-    let azure_blob_account_name =
-        std::env::var("STORAGE_ACCOUNT").expect("AZURE_BLOB_ACCOUNT_NAME must be set");
-    let azure_blob_container_name =
-        std::env::var("STORAGE_CONTAINER").expect("AZURE_BLOB_CONTAINER_NAME must be set");
+    // Command line tools instead of the server, e.g. `kafka-repo drift`
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args).await;
+    }
 
-    // JWT Secret Key
-    let jwt_secret_key = std::env::var("JWT_SECRET_KEY").expect("JWT_SECRET must be set");
+    // Read environment variables
+    let dataset_config = data_reload::dataset_config_from_env();
+
+    // Keys for the tokens issued by login, HS256 with JWT_SECRET_KEY unless configured otherwise
+    let key_config = signing_keys::KeyConfig::from_env().expect("Invalid JWT key configuration");
+    let key_rotation_check_secs = std::env::var("JWT_KEY_ROTATION_CHECK_SECS")
+        .unwrap_or("3600".to_string())
+        .parse::<u64>()
+        .expect("JWT_KEY_ROTATION_CHECK_SECS must be a number of seconds");
+    let signing_keys = Arc::new(
+        signing_keys::KeyRing::new(key_config)
+            .unwrap_or_else(|e| panic!("Failed to load JWT signing keys: {}", e)),
+    );
     // Azure AI Search
     let ai_search_api_url =
         std::env::var("AI_SEARCH_SERVICE_URL").expect("AI_SEARCH_URL must be set");
@@ -148,30 +202,71 @@ async fn main() -> std::io::Result<()> {
         .parse::<bool>()
         .expect("AI_SEARCH_WITH_SEMANTIC must be a boolean");
 
+    // Interval for reloading the kafka datasets, 0 disables the background refresh
+    let reload_interval_secs = std::env::var("DATASET_RELOAD_INTERVAL_SECS")
+        .unwrap_or("0".to_string())
+        .parse::<u64>()
+        .expect("DATASET_RELOAD_INTERVAL_SECS must be a number of seconds");
+
     // Open AI
     let open_ai_url = std::env::var("OPEN_AI_SERVICE_URL").expect("OPENAI_URL must be set");
     let open_api_key = std::env::var("OPEN_AI_KEY").expect("OPENAI_KEY must be set");
 
-    debug!("Reading kafka inventory file: {}", kafka_inventory_file);
-    debug!("Reading kafka consumer file: {}", kafka_consumer_file);
-    debug!("Azure Blob Storage account: {}", azure_blob_account_name);
     debug!(
-        "Azure Blob Storage container: {}",
-        azure_blob_container_name
+        "Reading kafka inventory from: {}",
+        dataset_config.kafka_inventory_source.describe()
+    );
+    debug!(
+        "Reading kafka consumer from: {}",
+        dataset_config.kafka_consumer_source.describe()
     );
     debug!("AI Search URL : {}", ai_search_api_url);
     debug!("Open AI Search URL : {}", open_ai_url);
 
-    let knowledge = load_mq_knowledge("dataset/mq_data.json");
+    let (knowledge, mq_topics) = load_mq_knowledge("dataset/mq_data.json");
 
     debug!("AI Search Indexes: {}", ai_search_indexes);
     let azure_index = serde_json::from_str::<Vec<AISearchIndex>>(&ai_search_indexes)
         .expect("Failed to parse index");
-    let mut data_state = data_state::AppState {
-        kafka_inventory: None,
-        kafka_consumer: None,
-        user_authentication: None,
-        jwt_secret: jwt_secret_key.clone(),
+    // Fetch the kafka inventory and consumer datasets, the server can't start without them
+    let (datasets, reload_status) = data_reload::load_datasets(&dataset_config)
+        .await
+        .expect("Failed to load kafka datasets");
+    // User authentication from USER_AUTHENTICATION_FILE and / or an OIDC identity provider
+    let auth_provider = auth_provider::auth_provider_from_env()
+        .await
+        .unwrap_or_else(|e| panic!("Failed to set up user authentication: {}", e));
+    let oidc = match oidc::OidcConfig::from_env().expect("Invalid OIDC configuration") {
+        Some(config) => Some(Arc::new(
+            oidc::OidcProvider::discover(config)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to set up OIDC login: {}", e)),
+        )),
+        None => None,
+    };
+    if auth_provider.is_none() && oidc.is_none() {
+        panic!("USER_AUTHENTICATION_FILE or OIDC_ISSUER_URL must be set");
+    }
+    let token_store = Arc::new(
+        token_store::TokenStore::from_env(signing_keys.access_token_ttl())
+            .unwrap_or_else(|e| panic!("Failed to open the token store: {}", e)),
+    );
+    let naming_rules = health_report::NamingRules::from_env()
+        .unwrap_or_else(|e| panic!("Invalid naming convention: {}", e));
+    let api_keys = Arc::new(
+        api_keys::ApiKeyStore::from_env()
+            .unwrap_or_else(|e| panic!("Failed to open the API key store: {}", e)),
+    );
+
+    let data_state = data_state::AppState {
+        datasets: Arc::new(RwLock::new(Arc::new(datasets))),
+        dataset_config,
+        reload_status: Arc::new(RwLock::new(reload_status)),
+        auth_provider,
+        oidc: oidc.clone(),
+        token_store: token_store.clone(),
+        api_keys: api_keys.clone(),
+        signing_keys: signing_keys.clone(),
         // Azure AI Search
         azure_ai_search_url: Some(ai_search_api_url),
         azure_ai_search_key: Some(ai_search_api_key),
@@ -181,82 +276,12 @@ async fn main() -> std::io::Result<()> {
 
         // static knowledge
         knowledge: Some(knowledge),
+        mq_topics,
+        naming_rules,
         // Open AI
         open_ai_config: create_openai(&open_ai_url, &open_api_key),
     };
 
-    // Fetch the dataset from Azure Blob Storage
-    let ds_inventory = fetch_dataset_az_blob(
-        &azure_blob_account_name,
-        &azure_blob_container_name,
-        &kafka_inventory_file,
-    )
-    .await;
-
-    // Fetch the dataset from Azure Blob Storage
-    let ds_consumer = fetch_dataset_az_blob(
-        &azure_blob_account_name,
-        &azure_blob_container_name,
-        &kafka_consumer_file,
-    )
-    .await;
-
-    // Fetch the dataset from Azure Blob Storage
-    let ds_user_authentication = fetch_dataset_az_blob(
-        &azure_blob_account_name,
-        &azure_blob_container_name,
-        &user_authentication_file,
-    )
-    .await;
-
-    // Check if the dataset was fetched successfully
-    match ds_inventory {
-        Ok(ds) => {
-            data_state.kafka_inventory = Some(ds);
-        }
-        Err(e) => {
-            //panic!("Failed to fetch kafka inventory from Azure Blob Storage: {}", e);
-            error!(
-                "Failed to fetch kafka inventory from Azure Blob Storage: {}",
-                e
-            );
-            let ds_kafka_inventory =
-                read_csv(&kafka_inventory_file).expect("Failed to read kafka inventory file");
-            data_state.kafka_inventory = Some(ds_kafka_inventory);
-        }
-    }
-
-    // Check if the dataset was fetched successfully
-    match ds_consumer {
-        Ok(ds) => {
-            data_state.kafka_consumer = Some(ds);
-        }
-        Err(e) => {
-            //panic!("Failed to fetch kafka consumer from Azure Blob Storage: {}", e);
-            error!(
-                "Failed to fetch kafka consumer from Azure Blob Storage: {}",
-                e
-            );
-            let ds_kafka_consumer =
-                read_csv(&kafka_consumer_file).expect("Failed to read kafka consumer file");
-            data_state.kafka_consumer = Some(ds_kafka_consumer);
-        }
-    }
-
-    // Check if the dataset was fetched successfully
-    match ds_user_authentication {
-        Ok(ds) => {
-            data_state.user_authentication = Some(ds);
-        }
-        Err(e) => {
-            //panic!("Failed to fetch kafka consumer from Azure Blob Storage: {}", e);
-            panic!(
-                "Failed to fetch user authentication from Azure Blob Storage: {}",
-                e
-            );
-        }
-    }
-
     // Rate Limit
     let limit = LimitBuilder::new().set_ttl(10).set_amount(20).build();
     // Rate Limiter
@@ -266,42 +291,91 @@ async fn main() -> std::io::Result<()> {
                 .set_path("/api/v1/ai_search")
                 .set_method("POST")
                 .build(),
-            limit.clone(),
+            limit,
         )
         .add_route(
             RouteBuilder::new()
                 .set_path("/api/v1/search")
                 .set_method("POST")
                 .build(),
-            limit.clone(),
+            limit,
         )
         .add_route(
             RouteBuilder::new()
                 .set_path("/api/v1/render")
                 .set_method("POST")
                 .build(),
-            limit.clone(),
+            limit,
         )
         .add_route(
             RouteBuilder::new()
                 .set_path("/api/v1/apps")
                 .set_method("GET")
                 .build(),
-            limit.clone(),
+            limit,
         )
         .add_route(
             RouteBuilder::new()
                 .set_path("/api/v1/apps/{appName}/topics")
                 .set_method("GET")
                 .build(),
-            limit.clone(),
+            limit,
+        )
+        .add_route(
+            RouteBuilder::new()
+                .set_path("/api/v1/apps/{appName}")
+                .set_method("GET")
+                .build(),
+            limit,
+        )
+        .add_route(
+            RouteBuilder::new()
+                .set_path("/api/v1/topics/{name}")
+                .set_method("GET")
+                .build(),
+            limit,
+        )
+        .add_route(
+            RouteBuilder::new()
+                .set_path("/api/v1/lineage/{appName}")
+                .set_method("GET")
+                .build(),
+            limit,
+        )
+        .add_route(
+            RouteBuilder::new()
+                .set_path("/api/v1/impact")
+                .set_method("POST")
+                .build(),
+            limit,
         )
         .add_route(
             RouteBuilder::new()
                 .set_path("/api/v1/consumers")
                 .set_method("GET")
                 .build(),
-            limit.clone(),
+            limit,
+        )
+        .add_route(
+            RouteBuilder::new()
+                .set_path("/api/v1/admin/reload")
+                .set_method("POST")
+                .build(),
+            limit,
+        )
+        .add_route(
+            RouteBuilder::new()
+                .set_path("/api/authenticate/v1/refresh")
+                .set_method("POST")
+                .build(),
+            limit,
+        )
+        .add_route(
+            RouteBuilder::new()
+                .set_path("/api/authenticate/v1/oidc/login")
+                .set_method("GET")
+                .build(),
+            limit,
         )
         .build();
 
@@ -309,12 +383,37 @@ async fn main() -> std::io::Result<()> {
     let rate_limiter = RateLimiterMiddlewareFactory::new(limiter, Arc::new(Mutex::new(backend)));
 
     let app_state = Arc::new(data_state);
+    if reload_interval_secs > 0 {
+        data_reload::spawn_refresher(
+            app_state.clone(),
+            Duration::from_secs(reload_interval_secs),
+        );
+    }
+    api_keys::spawn_last_used_flush(api_keys.clone());
+    if key_rotation_check_secs > 0 {
+        signing_keys::spawn_key_rotation(
+            signing_keys.clone(),
+            Duration::from_secs(key_rotation_check_secs),
+        );
+    }
     info!("Starting server...");
     actix_web::HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
+            .wrap(Logger::new(
+                r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{X-Request-ID}i"#,
+            ))
             .wrap(middleware::DefaultHeaders::new().add(("X-Version", "0.2")))
             .app_data(Data::new(app_state.clone()))
+            // malformed bodies, paths and query strings get the same JSON errors as the handlers
+            .app_data(web::JsonConfig::default().error_handler(|e, _| {
+                APIError::with_code(ErrorCode::InvalidRequest, &e.to_string()).into()
+            }))
+            .app_data(web::PathConfig::default().error_handler(|e, _| {
+                APIError::with_code(ErrorCode::InvalidRequest, &e.to_string()).into()
+            }))
+            .app_data(web::QueryConfig::default().error_handler(|e, _| {
+                APIError::with_code(ErrorCode::InvalidRequest, &e.to_string()).into()
+            }))
             .wrap_fn(|req, srv| {
                 let fut = srv.call(req);
                 async {
@@ -342,7 +441,12 @@ async fn main() -> std::io::Result<()> {
                     .allowed_methods(vec!["GET", "POST"]),
             )
             .wrap(rate_limiter.clone())
-            .wrap(jwt_middleware::JwtMiddleware::new(jwt_secret_key.clone()))
+            .wrap(jwt_middleware::JwtMiddleware::new(
+                signing_keys.clone(),
+                oidc.clone(),
+                token_store.clone(),
+                api_keys.clone(),
+            ))
             .wrap(
                 DefaultHeaders::new()
                     .add(("X-Content-Type-Options", "nosniff"))
@@ -353,21 +457,18 @@ async fn main() -> std::io::Result<()> {
                         "max-age=31536000; includeSubDomains",
                     )),
             )
+            .wrap(request_id::RequestIdMiddleware)
+            .service(web::scope("/api/v1").configure(api_v1_routes))
             .service(
-                web::scope("/api/v1")
-                    .route("/apps", web::get().to(apis::get_apps))
-                    .route("/apps/{appName}/topics", web::get().to(apis::get_topics))
-                    .route("/consumers", web::get().to(apis::get_consumers))
-                    .route("/search", web::post().to(apis::post_search_kafka))
-                    .route("/ai_search", web::post().to(apis::post_ai_search))
-                    .route(
-                        "/render",
-                        web::post().to(apis::post_topic_kafka_relation_render),
-                    ),
-            )
-            .service(
-                web::scope("/api/authenticate/v1").route("/login", web::post().to(apis::login)),
+                web::scope("/api/authenticate/v1")
+                    .route("/login", web::post().to(apis::login))
+                    .route("/refresh", web::post().to(apis::post_refresh_token))
+                    .route("/logout", web::post().to(apis::post_logout))
+                    .route("/methods", web::get().to(apis::get_login_methods))
+                    .route("/oidc/login", web::get().to(apis::get_oidc_login))
+                    .route("/oidc/callback", web::get().to(apis::get_oidc_callback)),
             )
+            .route("/.well-known/jwks.json", web::get().to(apis::get_jwks))
             .service(
                 fs::Files::new("/", "./statics")
                     .index_file("login.html")
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::authorization::Role;
+use crate::entities::{APIError, Claims, ErrorCode};
+use crate::token_store::random_token;
+
+// How long a login started at the IdP may take to come back to the callback
+const PENDING_LOGIN_TTL_SECS: i64 = 600;
+// Logins started and not back yet, anyone can start one so the map is capped
+const MAX_PENDING_LOGINS: usize = 10_000;
+// Unknown key ids trigger a JWKS refetch at most this often
+const JWKS_REFRESH_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+    // ID token claim holding the user's groups
+    pub groups_claim: String,
+    // IdP group -> role, users get the highest role of their groups
+    pub group_roles: HashMap<String, Role>,
+    // Role of users in none of the mapped groups, None to refuse them
+    pub default_role: Option<Role>,
+}
+
+impl OidcConfig {
+    /// Read `OIDC_ISSUER_URL`, `OIDC_CLIENT_ID`, `OIDC_CLIENT_SECRET`, `OIDC_REDIRECT_URL`,
+    /// `OIDC_SCOPES`, `OIDC_GROUPS_CLAIM`, `OIDC_GROUP_ROLES` (JSON object of group to role)
+    /// and `OIDC_DEFAULT_ROLE` (`none` to refuse unmapped users); `None` if `OIDC_ISSUER_URL` isn't set.
+    pub fn from_env() -> Result<Option<OidcConfig>, APIError> {
+        let issuer_url = match std::env::var("OIDC_ISSUER_URL") {
+            Ok(url) if !url.is_empty() => url,
+            _ => return Ok(None),
+        };
+        let required = |name: &str| {
+            std::env::var(name).map_err(|_| {
+                APIError::new(&format!("{} must be set when OIDC_ISSUER_URL is set", name))
+            })
+        };
+        let group_roles = match std::env::var("OIDC_GROUP_ROLES") {
+            Ok(json) if !json.is_empty() => serde_json::from_str::<HashMap<String, Role>>(&json)
+                .map_err(|e| APIError::new(&format!("Invalid OIDC_GROUP_ROLES: {}", e)))?,
+            _ => HashMap::new(),
+        };
+        let default_role = match std::env::var("OIDC_DEFAULT_ROLE") {
+            Ok(role) if role.eq_ignore_ascii_case("none") => None,
+            Ok(role) => Some(
+                role.parse::<Role>()
+                    .map_err(|e| APIError::new(&format!("Invalid OIDC_DEFAULT_ROLE: {}", e)))?,
+            ),
+            Err(_) => Some(Role::Viewer),
+        };
+        Ok(Some(OidcConfig {
+            issuer_url,
+            client_id: required("OIDC_CLIENT_ID")?,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_url: required("OIDC_REDIRECT_URL")?,
+            scopes: std::env::var("OIDC_SCOPES").unwrap_or("openid profile email".to_string()),
+            groups_claim: std::env::var("OIDC_GROUPS_CLAIM").unwrap_or("groups".to_string()),
+            group_roles,
+            default_role,
+        }))
+    }
+}
+
+/// The parts of `/.well-known/openid-configuration` used here.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiscoveryDocument {
+    #[serde(rename = "issuer")]
+    pub issuer: String,
+    #[serde(rename = "authorization_endpoint")]
+    pub authorization_endpoint: String,
+    #[serde(rename = "token_endpoint")]
+    pub token_endpoint: String,
+    #[serde(rename = "jwks_uri")]
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    #[serde(rename = "id_token")]
+    id_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    #[serde(rename = "sub")]
+    sub: String,
+    #[serde(rename = "exp")]
+    exp: usize,
+    #[serde(rename = "nonce")]
+    nonce: Option<String>,
+    #[serde(rename = "iat")]
+    iat: Option<usize>,
+    #[serde(rename = "jti")]
+    jti: Option<String>,
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+struct PendingLogin {
+    nonce: String,
+    started_at: DateTime<Utc>,
+}
+
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: DateTime<Utc>,
+}
+
+/// A successful login at the identity provider.
+pub struct OidcLogin {
+    pub id_token: String,
+    pub claims: Claims,
+}
+
+/// Relying party for one OpenID Connect issuer: authorization code flow and ID token validation.
+pub struct OidcProvider {
+    config: OidcConfig,
+    discovery: DiscoveryDocument,
+    jwks: RwLock<CachedJwks>,
+    // state -> nonce of logins sent to the IdP and not back yet
+    pending: Mutex<HashMap<String, PendingLogin>>,
+    client: reqwest::Client,
+}
+
+impl OidcProvider {
+    /// Read the issuer's discovery document and signing keys.
+    pub async fn discover(config: OidcConfig) -> Result<OidcProvider, APIError> {
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            config.issuer_url.trim_end_matches('/')
+        );
+        debug!("Fetching OIDC discovery document: {}", url);
+        let discovery: DiscoveryDocument = fetch_json(&client, &url).await?;
+        if discovery.issuer.trim_end_matches('/') != config.issuer_url.trim_end_matches('/') {
+            return Err(APIError::new(&format!(
+                "OIDC discovery document is for issuer {}, expected {}",
+                discovery.issuer, config.issuer_url
+            )));
+        }
+        let keys: JwkSet = fetch_json(&client, &discovery.jwks_uri).await?;
+        info!(
+            "OIDC issuer {} with {} signing keys",
+            discovery.issuer,
+            keys.keys.len()
+        );
+        Ok(OidcProvider {
+            config,
+            discovery,
+            jwks: RwLock::new(CachedJwks {
+                keys,
+                fetched_at: Utc::now(),
+            }),
+            pending: Mutex::new(HashMap::new()),
+            client,
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.discovery.issuer
+    }
+
+    /// Start a login: remember a fresh state / nonce pair and return the IdP URL to redirect to.
+    pub fn authorization_url(&self) -> Result<String, APIError> {
+        let state = random_token(32);
+        let nonce = random_token(32);
+        let url = reqwest::Url::parse_with_params(
+            &self.discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("scope", self.config.scopes.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+            ],
+        )
+        .map_err(|e| APIError::new(&format!("Invalid OIDC authorization endpoint: {}", e)))?;
+
+        let now = Utc::now();
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= MAX_PENDING_LOGINS {
+            pending.retain(|_, p| now - p.started_at < Duration::seconds(PENDING_LOGIN_TTL_SECS));
+        }
+        if pending.len() >= MAX_PENDING_LOGINS {
+            error!(
+                "{} OIDC logins in progress, refusing new ones",
+                pending.len()
+            );
+            return Err(APIError::with_code(
+                ErrorCode::TooManyRequests,
+                "Too many logins in progress, please try again later",
+            ));
+        }
+        pending.insert(
+            state,
+            PendingLogin {
+                nonce,
+                started_at: now,
+            },
+        );
+        Ok(url.to_string())
+    }
+
+    /// Finish a login on the redirect back from the IdP: check `state`, exchange `code` for
+    /// tokens and validate the ID token.
+    pub async fn finish_login(&self, code: &str, state: &str) -> Result<OidcLogin, APIError> {
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|p| Utc::now() - p.started_at < Duration::seconds(PENDING_LOGIN_TTL_SECS))
+            .ok_or_else(|| {
+                APIError::with_code(
+                    ErrorCode::Unauthorized,
+                    "Unknown or expired login, please sign in again",
+                )
+            })?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let response = self
+            .client
+            .post(&self.discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                error!("OIDC token request failed: {}", e);
+                APIError::with_code(
+                    ErrorCode::Unauthorized,
+                    "Failed to redeem the authorization code",
+                )
+            })?;
+        let tokens: TokenResponse = response.json().await.map_err(|e| {
+            error!("Invalid OIDC token response: {}", e);
+            APIError::with_code(
+                ErrorCode::UpstreamUnavailable,
+                "Failed to redeem the authorization code",
+            )
+        })?;
+        let id_token = tokens.id_token.ok_or_else(|| {
+            APIError::with_code(
+                ErrorCode::UpstreamUnavailable,
+                "The identity provider returned no ID token",
+            )
+        })?;
+
+        let claims = self.validate(&id_token, Some(&pending.nonce)).await?;
+        Ok(OidcLogin { id_token, claims })
+    }
+
+    /// Validate an ID token from this issuer and map it into `Claims`.
+    pub async fn validate(&self, token: &str, nonce: Option<&str>) -> Result<Claims, APIError> {
+        let header = decode_header(token).map_err(|e| {
+            APIError::with_code(ErrorCode::Unauthorized, &format!("Invalid ID token: {}", e))
+        })?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(APIError::with_code(
+                ErrorCode::Unauthorized,
+                "ID tokens must be signed with the issuer's keys",
+            ));
+        }
+
+        let key = match self.decoding_key(header.kid.as_deref())? {
+            Some(key) => key,
+            None => {
+                // the issuer may have rotated its keys since the last fetch
+                self.refresh_jwks().await?;
+                self.decoding_key(header.kid.as_deref())?.ok_or_else(|| {
+                    APIError::with_code(
+                        ErrorCode::Unauthorized,
+                        &format!("No signing key {:?} at the issuer", header.kid),
+                    )
+                })?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&self.discovery.issuer]);
+        let data = decode::<IdTokenClaims>(token, &key, &validation).map_err(|e| {
+            APIError::with_code(ErrorCode::Unauthorized, &format!("Invalid ID token: {}", e))
+        })?;
+        let id_claims = data.claims;
+        if nonce.is_some() && id_claims.nonce.as_deref() != nonce {
+            return Err(APIError::with_code(
+                ErrorCode::Unauthorized,
+                "Invalid ID token: nonce mismatch",
+            ));
+        }
+
+        let groups = match id_claims.other.get(&self.config.groups_claim) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect(),
+            Some(Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        };
+        let mut roles: Vec<Role> = groups
+            .iter()
+            .filter_map(|g| self.config.group_roles.get(g).copied())
+            .collect();
+        if roles.is_empty() {
+            roles.extend(self.config.default_role);
+        }
+        Ok(Claims::new(
+            id_claims.sub,
+            id_claims.exp,
+            self.discovery.issuer.clone(),
+            self.config.client_id.clone(),
+        )
+        .with_groups(groups)
+        .with_roles(roles)
+        .with_issued(id_claims.iat, id_claims.jti.unwrap_or_default()))
+    }
+
+    // None if there is no matching key; symmetric keys are never accepted from a JWKS
+    fn decoding_key(&self, kid: Option<&str>) -> Result<Option<DecodingKey>, APIError> {
+        let jwks = self.jwks.read().unwrap();
+        let jwk = match kid {
+            Some(kid) => jwks.keys.find(kid),
+            None if jwks.keys.keys.len() == 1 => jwks.keys.keys.first(),
+            None => None,
+        };
+        match jwk {
+            Some(jwk) if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) => Err(
+                APIError::new("The issuer published a symmetric key, refusing to use it"),
+            ),
+            Some(jwk) => DecodingKey::from_jwk(jwk)
+                .map(Some)
+                .map_err(|e| APIError::new(&format!("Invalid issuer signing key: {}", e))),
+            None => Ok(None),
+        }
+    }
+
+    async fn refresh_jwks(&self) -> Result<(), APIError> {
+        let fetched_at = self.jwks.read().unwrap().fetched_at;
+        if Utc::now() - fetched_at < Duration::seconds(JWKS_REFRESH_INTERVAL_SECS) {
+            return Ok(());
+        }
+        debug!("Refreshing JWKS from {}", self.discovery.jwks_uri);
+        let keys: JwkSet = fetch_json(&self.client, &self.discovery.jwks_uri).await?;
+        *self.jwks.write().unwrap() = CachedJwks {
+            keys,
+            fetched_at: Utc::now(),
+        };
+        Ok(())
+    }
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<T, APIError> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| {
+            APIError::with_code(
+                ErrorCode::UpstreamUnavailable,
+                &format!("Failed to fetch {}: {}", url, e),
+            )
+        })?;
+    response.json().await.map_err(|e| {
+        APIError::with_code(
+            ErrorCode::UpstreamUnavailable,
+            &format!("Invalid JSON from {}: {}", url, e),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::{json, Value};
+
+    use super::{OidcConfig, OidcProvider};
+    use crate::authorization::Role;
+    use crate::entities::ErrorCode;
+
+    const CLIENT_ID: &str = "kafka-repo";
+
+    struct MockIssuer {
+        url: String,
+        key: EncodingKey,
+        jwks: Value,
+    }
+
+    async fn discovery(issuer: web::Data<MockIssuer>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "issuer": issuer.url,
+            "authorization_endpoint": format!("{}/authorize", issuer.url),
+            "token_endpoint": format!("{}/token", issuer.url),
+            "jwks_uri": format!("{}/jwks", issuer.url),
+        }))
+    }
+
+    async fn jwks(issuer: web::Data<MockIssuer>) -> HttpResponse {
+        HttpResponse::Ok().json(&issuer.jwks)
+    }
+
+    // Redeems any code, with the code as the nonce of the ID token
+    async fn token(
+        issuer: web::Data<MockIssuer>,
+        form: web::Form<HashMap<String, String>>,
+    ) -> HttpResponse {
+        let now = chrono::Utc::now().timestamp();
+        let claims = json!({
+            "iss": issuer.url,
+            "aud": CLIENT_ID,
+            "sub": "alice",
+            "iat": now,
+            "exp": now + 600,
+            "nonce": form.get("code"),
+            "groups": ["kafka-admins", "staff"],
+        });
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some("mock-key".to_string());
+        let id_token = encode(&header, &claims, &issuer.key).unwrap();
+        HttpResponse::Ok().json(json!({ "id_token": id_token, "token_type": "Bearer" }))
+    }
+
+    // A local issuer serving discovery, JWKS and the token endpoint, returns its URL
+    fn start_mock_issuer() -> String {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+        let point = key_pair.public_key().as_ref();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let issuer = web::Data::new(MockIssuer {
+            url: url.clone(),
+            key: EncodingKey::from_ec_der(pkcs8.as_ref()),
+            jwks: json!({ "keys": [{
+                "kty": "EC",
+                "crv": "P-256",
+                "kid": "mock-key",
+                "use": "sig",
+                "alg": "ES256",
+                "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+            }]}),
+        });
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(issuer.clone())
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(discovery),
+                )
+                .route("/jwks", web::get().to(jwks))
+                .route("/token", web::post().to(token))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+        url
+    }
+
+    fn config(issuer_url: &str) -> OidcConfig {
+        OidcConfig {
+            issuer_url: issuer_url.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost:8888/api/authenticate/v1/oidc/callback".to_string(),
+            scopes: "openid profile".to_string(),
+            groups_claim: "groups".to_string(),
+            group_roles: HashMap::from([("kafka-admins".to_string(), Role::Admin)]),
+            default_role: Some(Role::Viewer),
+        }
+    }
+
+    // state and nonce of a login started at the provider
+    fn start_login(provider: &OidcProvider) -> (String, String) {
+        let url = reqwest::Url::parse(&provider.authorization_url().unwrap()).unwrap();
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_string())
+                .unwrap()
+        };
+        assert_eq!(param("client_id"), CLIENT_ID);
+        assert_eq!(param("response_type"), "code");
+        (param("state"), param("nonce"))
+    }
+
+    #[actix_web::test]
+    async fn discovers_the_issuer() {
+        let url = start_mock_issuer();
+        let provider = OidcProvider::discover(config(&url)).await.unwrap();
+        assert_eq!(provider.issuer(), url);
+        assert!(provider
+            .authorization_url()
+            .unwrap()
+            .starts_with(&format!("{}/authorize?", url)));
+
+        let wrong_issuer = config(&format!("{}/other", url));
+        assert!(OidcProvider::discover(wrong_issuer).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn exchanges_the_code_for_claims() {
+        let url = start_mock_issuer();
+        let provider = OidcProvider::discover(config(&url)).await.unwrap();
+        let (state, nonce) = start_login(&provider);
+
+        let login = provider.finish_login(&nonce, &state).await.unwrap();
+        assert_eq!(login.claims.sub(), "alice");
+        assert_eq!(login.claims.groups(), ["kafka-admins", "staff"]);
+        assert_eq!(login.claims.roles(), [Role::Admin]);
+        // the ID token is accepted as a bearer token too
+        let claims = provider.validate(&login.id_token, None).await.unwrap();
+        assert_eq!(claims.sub(), "alice");
+    }
+
+    #[actix_web::test]
+    async fn rejects_unknown_state_and_wrong_nonce() {
+        let url = start_mock_issuer();
+        let provider = OidcProvider::discover(config(&url)).await.unwrap();
+
+        let (state, nonce) = start_login(&provider);
+        let e = provider
+            .finish_login(&nonce, "forged-state")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(e.code(), ErrorCode::Unauthorized);
+
+        // the mock issues the code as nonce, so another code is a nonce mismatch
+        let e = provider
+            .finish_login("other-nonce", &state)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(e.code(), ErrorCode::Unauthorized);
+        assert!(e.to_string().contains("nonce"));
+
+        // a state is only good for one callback
+        let e = provider.finish_login(&nonce, &state).await.err().unwrap();
+        assert_eq!(e.code(), ErrorCode::Unauthorized);
+    }
+}
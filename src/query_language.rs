@@ -0,0 +1,474 @@
+use log::debug;
+use pest::error::{InputLocation, LineColLocation};
+use pest::iterators::Pair;
+use pest::Parser;
+use pest_derive::Parser;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::entities::{APIError, ErrorCode};
+
+#[derive(Parser)]
+#[grammar = "../boolean_expr.pest"]
+struct BooleanExprParser;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueryError {
+    #[serde(rename = "message")]
+    pub message: String,
+    #[serde(rename = "line")]
+    pub line: usize,
+    #[serde(rename = "column")]
+    pub column: usize,
+    #[serde(rename = "offset")]
+    pub offset: usize,
+}
+
+impl QueryError {
+    fn at(pair: &Pair<Rule>, message: String) -> Self {
+        let span = pair.as_span();
+        let (line, column) = span.start_pos().line_col();
+        QueryError {
+            message,
+            line,
+            column,
+            offset: span.start(),
+        }
+    }
+}
+
+impl From<pest::error::Error<Rule>> for QueryError {
+    fn from(e: pest::error::Error<Rule>) -> Self {
+        let e = e.renamed_rules(|rule| match rule {
+            Rule::ident => "column name".to_string(),
+            Rule::value | Rule::quoted => "value".to_string(),
+            Rule::cmp_op => "=, != or ~".to_string(),
+            Rule::not_op => "not".to_string(),
+            Rule::in_op => "in".to_string(),
+            Rule::is_op => "is".to_string(),
+            Rule::null_kw => "null".to_string(),
+            Rule::or_op => "or".to_string(),
+            Rule::and_op => "and".to_string(),
+            Rule::paren_expr => "(".to_string(),
+            Rule::comparison | Rule::null_check | Rule::in_list => "comparison".to_string(),
+            Rule::EOI => "end of query".to_string(),
+            other => format!("{:?}", other),
+        });
+        let (line, column) = match e.line_col {
+            LineColLocation::Pos(pos) => pos,
+            LineColLocation::Span(start, _) => start,
+        };
+        let offset = match e.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        QueryError {
+            message: e.variant.message().to_string(),
+            line,
+            column,
+            offset,
+        }
+    }
+}
+
+impl From<QueryError> for APIError {
+    fn from(e: QueryError) -> Self {
+        let message = format!(
+            "Invalid query at line {} column {}: {}",
+            e.line, e.column, e.message
+        );
+        APIError::with_code(ErrorCode::InvalidQuery, &message)
+            .with_details(serde_json::to_value(&e).ok())
+    }
+}
+
+/// Parse a boolean query such as
+/// `Project=Payments and (Consumer_App=Ledger or Consumer_App=Fraud)`
+/// and compile it into a polars expression over the columns in `schema`.
+///
+/// Supported predicates are `=`, `!=`, `~` (regex / substring), `in (a, b)`, `not in (a, b)`,
+/// `is null` and `is not null`, combined with `and`, `or`, `not` and parentheses.
+/// Values containing spaces, dots or dashes may be quoted with `"` or `'`.
+pub fn compile_query(query: &str, schema: &Schema) -> Result<Expr, QueryError> {
+    let mut pairs = BooleanExprParser::parse(Rule::boolean_expr, query)?;
+    let root = pairs.next().unwrap();
+    debug!("Parsed query: {:?}", root);
+
+    let expr = root
+        .into_inner()
+        .find(|p| p.as_rule() != Rule::EOI)
+        .unwrap();
+    compile_pair(expr, schema)
+}
+
+/// The columns of `schema` that `query` refers to, in the order they appear.
+pub fn query_columns(query: &str, schema: &Schema) -> Result<Vec<String>, QueryError> {
+    BooleanExprParser::parse(Rule::boolean_expr, query)?
+        .flatten()
+        .filter(|p| p.as_rule() == Rule::ident)
+        .map(|ident| resolve_column(&ident, schema))
+        .collect()
+}
+
+fn compile_pair(pair: Pair<Rule>, schema: &Schema) -> Result<Expr, QueryError> {
+    match pair.as_rule() {
+        Rule::or_expr => fold_operands(pair, schema, Expr::or),
+        Rule::and_expr => fold_operands(pair, schema, Expr::and),
+        Rule::paren_expr => compile_pair(pair.into_inner().next().unwrap(), schema),
+        Rule::not_expr => {
+            let operand = pair.into_inner().nth(1).unwrap();
+            Ok(compile_pair(operand, schema)?.not())
+        }
+        Rule::comparison => compile_comparison(pair, schema),
+        Rule::in_list => compile_in_list(pair, schema),
+        Rule::null_check => compile_null_check(pair, schema),
+        _ => Err(QueryError::at(
+            &pair,
+            format!("Unexpected token '{}'", pair.as_str()),
+        )),
+    }
+}
+
+fn fold_operands(
+    pair: Pair<Rule>,
+    schema: &Schema,
+    combine: fn(Expr, Expr) -> Expr,
+) -> Result<Expr, QueryError> {
+    let mut result: Option<Expr> = None;
+    for operand in pair.into_inner() {
+        if matches!(operand.as_rule(), Rule::or_op | Rule::and_op) {
+            continue;
+        }
+        let expr = compile_pair(operand, schema)?;
+        result = Some(match result {
+            Some(acc) => combine(acc, expr),
+            None => expr,
+        });
+    }
+    Ok(result.unwrap())
+}
+
+fn compile_comparison(pair: Pair<Rule>, schema: &Schema) -> Result<Expr, QueryError> {
+    let mut inner = pair.into_inner();
+    let ident = inner.next().unwrap();
+    let op = inner.next().unwrap();
+    let literal = inner.next().unwrap();
+
+    let column = string_column(&ident, schema)?;
+    let value = literal_value(&literal);
+    match op.as_str() {
+        "=" => Ok(column.eq(lit(value))),
+        "!=" => Ok(column.neq(lit(value))),
+        "~" => {
+            // Validate here so a bad pattern is reported at its position instead of
+            // failing later inside the polars plan.
+            regex::Regex::new(&value).map_err(|e| {
+                QueryError::at(&literal, format!("Invalid regular expression: {}", e))
+            })?;
+            Ok(column.str().contains(lit(value), false))
+        }
+        other => Err(QueryError::at(
+            &op,
+            format!("Unsupported operator '{}'", other),
+        )),
+    }
+}
+
+fn compile_in_list(pair: Pair<Rule>, schema: &Schema) -> Result<Expr, QueryError> {
+    let mut inner = pair.into_inner();
+    let ident = inner.next().unwrap();
+    let column = string_column(&ident, schema)?;
+
+    let mut negate = false;
+    let mut expr: Option<Expr> = None;
+    for item in inner {
+        match item.as_rule() {
+            Rule::not_op => negate = true,
+            Rule::in_op => {}
+            _ => {
+                let eq = column.clone().eq(lit(literal_value(&item)));
+                expr = Some(match expr {
+                    Some(acc) => acc.or(eq),
+                    None => eq,
+                });
+            }
+        }
+    }
+    let expr = expr.unwrap();
+    Ok(if negate { expr.not() } else { expr })
+}
+
+fn compile_null_check(pair: Pair<Rule>, schema: &Schema) -> Result<Expr, QueryError> {
+    let mut inner = pair.into_inner();
+    let ident = inner.next().unwrap();
+    let column = resolve_column(&ident, schema)?;
+    let negate = inner.any(|p| p.as_rule() == Rule::not_op);
+
+    Ok(if negate {
+        col(column.as_str()).is_not_null()
+    } else {
+        col(column.as_str()).is_null()
+    })
+}
+
+fn string_column(ident: &Pair<Rule>, schema: &Schema) -> Result<Expr, QueryError> {
+    let column = resolve_column(ident, schema)?;
+    Ok(col(column.as_str()).cast(DataType::String))
+}
+
+fn literal_value(literal: &Pair<Rule>) -> String {
+    match literal.as_rule() {
+        Rule::quoted => {
+            let raw = literal.clone().into_inner().next().unwrap().as_str();
+            unescape(raw)
+        }
+        _ => literal.as_str().trim().to_string(),
+    }
+}
+
+// Only quotes and backslashes are escapes, so regex escapes such as `\.` pass through untouched.
+fn unescape(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&next) = chars.peek() {
+                if next == '"' || next == '\'' || next == '\\' {
+                    result.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Column names are matched case-insensitively so `project=...` works as well as `Project=...`.
+fn resolve_column(ident: &Pair<Rule>, schema: &Schema) -> Result<String, QueryError> {
+    let name = ident.as_str();
+    schema
+        .iter_names()
+        .find(|c| c.eq_ignore_ascii_case(name))
+        .map(|c| c.to_string())
+        .ok_or_else(|| {
+            let columns = schema
+                .iter_names()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            QueryError::at(
+                ident,
+                format!("Unknown column '{}', expected one of: {}", name, columns),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topics() -> DataFrame {
+        df!(
+            "Project" => ["payments", "payments", "orders", "billing", "o'neil \"ops\""],
+            "Topic_Name_Kafka" => [
+                "payments.txn",
+                "payments.refund",
+                "orders.created",
+                "billing.invoice",
+                "payments.txn-events.v1",
+            ],
+            "Consumer_App" => [Some("ledger"), None, Some("ledger"), Some("fraud"), Some("audit")],
+        )
+        .unwrap()
+    }
+
+    // Topic names of the rows matching `query`
+    fn matching(query: &str) -> Result<Vec<String>, QueryError> {
+        let ds = topics();
+        let expr = compile_query(query, &ds.schema())?;
+        let ds = ds.lazy().filter(expr).collect().unwrap();
+        Ok(ds
+            .column("Topic_Name_Kafka")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .map(|t| t.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            matching("Project=orders or Project=payments and Consumer_App=ledger").unwrap(),
+            ["payments.txn", "orders.created"]
+        );
+        assert_eq!(
+            matching("Project=payments and Consumer_App=ledger or Project=billing").unwrap(),
+            ["payments.txn", "billing.invoice"]
+        );
+    }
+
+    #[test]
+    fn parentheses_group_first() {
+        assert_eq!(
+            matching("(Project=orders or Project=payments) and Consumer_App=ledger").unwrap(),
+            ["payments.txn", "orders.created"]
+        );
+        assert_eq!(
+            matching("Project=payments and (Consumer_App=fraud or Consumer_App=ledger)").unwrap(),
+            ["payments.txn"]
+        );
+        assert_eq!(
+            matching("((Project=billing))").unwrap(),
+            ["billing.invoice"]
+        );
+    }
+
+    #[test]
+    fn column_names_ignore_case() {
+        assert_eq!(
+            matching("project=orders").unwrap(),
+            matching("PROJECT=orders").unwrap()
+        );
+        assert_eq!(matching("consumer_app=fraud").unwrap(), ["billing.invoice"]);
+        assert_eq!(
+            matching("Project=billing AND consumer_app=fraud").unwrap(),
+            ["billing.invoice"]
+        );
+    }
+
+    #[test]
+    fn unknown_column_is_reported_where_it_is() {
+        let e = matching("Project=payments and Owner=ledger").unwrap_err();
+        assert_eq!(
+            e.message,
+            "Unknown column 'Owner', expected one of: Project, Topic_Name_Kafka, Consumer_App"
+        );
+        assert_eq!((e.line, e.column, e.offset), (1, 22, 21));
+    }
+
+    #[test]
+    fn parse_error_reports_line_and_column() {
+        let e = matching("Project=payments and\n  (Consumer_App=ledger or").unwrap_err();
+        assert_eq!((e.line, e.column), (2, 26));
+        assert_eq!(e.offset, 46);
+
+        let e = matching("Project payments").unwrap_err();
+        assert_eq!((e.line, e.column, e.offset), (1, 9, 8));
+        assert!(e.message.contains("=, != or ~"), "{}", e.message);
+
+        let error = APIError::from(e);
+        assert_eq!(error.code(), ErrorCode::InvalidQuery);
+    }
+
+    #[test]
+    fn quoted_values_unescape_quotes_and_backslashes() {
+        assert_eq!(
+            matching(r#"Project='o\'neil "ops"'"#).unwrap(),
+            ["payments.txn-events.v1"]
+        );
+        assert_eq!(
+            matching(r#"Project="o'neil \"ops\"""#).unwrap(),
+            ["payments.txn-events.v1"]
+        );
+        assert_eq!(
+            matching(r#"Project = "billing" and Consumer_App = 'fraud'"#).unwrap(),
+            ["billing.invoice"]
+        );
+        // regex escapes other than quotes and backslashes are kept
+        assert_eq!(unescape(r#"a\"b\'c\\d\.e"#), r#"a"b'c\d\.e"#);
+    }
+
+    #[test]
+    fn bare_values_may_hold_dots_and_dashes() {
+        assert_eq!(
+            matching("Topic_Name_Kafka=payments.txn-events.v1").unwrap(),
+            ["payments.txn-events.v1"]
+        );
+        assert_eq!(
+            matching("Topic_Name_Kafka=payments.txn-events.v1 or Project=orders").unwrap(),
+            ["orders.created", "payments.txn-events.v1"]
+        );
+    }
+
+    #[test]
+    fn not_equal_skips_nulls() {
+        assert_eq!(
+            matching("Consumer_App!=ledger").unwrap(),
+            ["billing.invoice", "payments.txn-events.v1"]
+        );
+    }
+
+    #[test]
+    fn tilde_matches_a_regex() {
+        assert_eq!(
+            matching(r#"Topic_Name_Kafka~"^payments\.txn""#).unwrap(),
+            ["payments.txn", "payments.txn-events.v1"]
+        );
+        assert_eq!(
+            matching("Topic_Name_Kafka~created").unwrap(),
+            ["orders.created"]
+        );
+
+        let e = matching(r#"Project=payments and Topic_Name_Kafka~"(txn""#).unwrap_err();
+        assert!(
+            e.message.starts_with("Invalid regular expression"),
+            "{}",
+            e.message
+        );
+        assert_eq!((e.line, e.column), (1, 39));
+    }
+
+    #[test]
+    fn in_lists_and_not_in_lists() {
+        assert_eq!(
+            matching("Project in (orders, billing)").unwrap(),
+            ["orders.created", "billing.invoice"]
+        );
+        assert_eq!(
+            matching("Topic_Name_Kafka in ('payments.txn', payments.txn-events.v1)").unwrap(),
+            ["payments.txn", "payments.txn-events.v1"]
+        );
+        assert_eq!(
+            matching("Project not in (payments, orders)").unwrap(),
+            ["billing.invoice", "payments.txn-events.v1"]
+        );
+    }
+
+    #[test]
+    fn not_negates_the_following_operand() {
+        assert_eq!(
+            matching("not Project=payments and Consumer_App=ledger").unwrap(),
+            ["orders.created"]
+        );
+        assert_eq!(
+            matching("not (Project=payments or Project=orders)").unwrap(),
+            ["billing.invoice", "payments.txn-events.v1"]
+        );
+    }
+
+    #[test]
+    fn null_checks() {
+        assert_eq!(
+            matching("Consumer_App is null").unwrap(),
+            ["payments.refund"]
+        );
+        assert_eq!(
+            matching("Consumer_App IS NOT NULL and Project=payments").unwrap(),
+            ["payments.txn"]
+        );
+    }
+
+    #[test]
+    fn query_columns_lists_the_referenced_columns() {
+        let schema = topics().schema();
+        assert_eq!(
+            query_columns("project=payments and not consumer_app is null", &schema).unwrap(),
+            ["Project", "Consumer_App"]
+        );
+        assert!(query_columns("Owner=payments", &schema).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_web::body::BoxBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::Error;
+use futures_util::future::{ok, FutureExt, LocalBoxFuture, Ready};
+
+use crate::entities::APIError;
+use crate::token_store::random_token;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Tags every request with the caller's `X-Request-ID`, or a new one, echoes it in the
+/// response and adds it to `APIError` bodies so clients can quote it when reporting a problem.
+pub struct RequestIdMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: actix_web::body::MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = RequestIdMiddlewareMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdMiddlewareMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct RequestIdMiddlewareMiddleware<S> {
+    service: Rc<RefCell<S>>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddlewareMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: actix_web::body::MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        // only pass on ids that are safe to log and echo back
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LEN
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+            })
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| random_token(20));
+        let header_value = HeaderValue::from_str(&request_id).unwrap();
+        // the access log reads it from the request headers
+        req.headers_mut().insert(
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            header_value.clone(),
+        );
+        let svc = self.service.clone();
+
+        async move {
+            let res = svc.call(req).await?.map_into_boxed_body();
+            let api_error = res
+                .response()
+                .error()
+                .and_then(|e| e.as_error::<APIError>())
+                .cloned();
+            let mut res = match api_error {
+                Some(api_error) => {
+                    let body = serde_json::to_string(&api_error.with_request_id(&request_id))
+                        .unwrap_or_default();
+                    res.map_body(|head, _| {
+                        head.headers_mut().insert(
+                            header::CONTENT_TYPE,
+                            HeaderValue::from_static("application/json"),
+                        );
+                        BoxBody::new(body)
+                    })
+                }
+                None => res,
+            };
+            res.headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), header_value);
+            Ok(res)
+        }
+        .boxed_local()
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashMap};
+
+use log::debug;
+use polars::prelude::*;
+
+use crate::data_service::{
+    COL_APP_OWNER_INVENTORY_FILE, COL_CONSUMER_APP_NAME_CONSUMER_FILE,
+    COL_CONSUMER_GROUP_NAME_CONSUMER_FILE, COL_DESCRIPTION_INVENTORY_FILE,
+    COL_TOPIC_NAME_INVENTORY_FILE,
+};
+use crate::dataset_schema::string_values;
+use crate::entities::APIError;
+
+// Scores of one query word against one indexed word
+const EXACT_SCORE: f64 = 1.0;
+const PREFIX_SCORE: f64 = 0.7;
+const TYPO_SCORE: f64 = 0.6;
+const TYPO_PREFIX_SCORE: f64 = 0.5;
+// each extra typo costs this much
+const TYPO_PENALTY: f64 = 0.1;
+
+/// Words of the topic, project, consumer group and description values of the datasets,
+/// for the offline `search_all_text`.
+#[derive(Default)]
+pub struct TextIndex {
+    words: Vec<String>,
+    // indexed value -> ids of its words
+    values: HashMap<String, Vec<usize>>,
+}
+
+impl TextIndex {
+    pub fn build(ds_inventory: &DataFrame, ds_consumer: &DataFrame) -> Result<TextIndex, APIError> {
+        let mut columns = vec![
+            (ds_inventory, COL_APP_OWNER_INVENTORY_FILE),
+            (ds_inventory, COL_TOPIC_NAME_INVENTORY_FILE),
+            (ds_consumer, COL_CONSUMER_APP_NAME_CONSUMER_FILE),
+            (ds_consumer, COL_CONSUMER_GROUP_NAME_CONSUMER_FILE),
+        ];
+        // descriptions are an optional inventory column
+        if ds_inventory
+            .get_column_names()
+            .contains(&COL_DESCRIPTION_INVENTORY_FILE)
+        {
+            columns.push((ds_inventory, COL_DESCRIPTION_INVENTORY_FILE));
+        }
+
+        let mut word_ids: BTreeMap<String, usize> = BTreeMap::new();
+        let mut index = TextIndex::default();
+        for (ds, column) in columns {
+            for value in string_values(ds, column)?.into_iter().flatten() {
+                if index.values.contains_key(&value) {
+                    continue;
+                }
+                let mut ids: Vec<usize> = tokenize(&value)
+                    .into_iter()
+                    .map(|word| {
+                        let next_id = word_ids.len();
+                        *word_ids.entry(word).or_insert(next_id)
+                    })
+                    .collect();
+                ids.dedup();
+                index.values.insert(value, ids);
+            }
+        }
+        index.words = vec![String::new(); word_ids.len()];
+        for (word, id) in word_ids {
+            index.words[id] = word;
+        }
+        debug!(
+            "Text index: {} values, {} words",
+            index.values.len(),
+            index.words.len()
+        );
+        Ok(index)
+    }
+
+    /// Match every word of `text` against the indexed words.
+    pub fn query(&self, text: &str) -> TextQuery<'_> {
+        let words = tokenize(text)
+            .iter()
+            .map(|query_word| {
+                self.words
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(id, word)| word_score(query_word, word).map(|score| (id, score)))
+                    .collect()
+            })
+            .collect();
+        TextQuery { index: self, words }
+    }
+}
+
+/// Scores of a query against the index, see `TextQuery::score`.
+pub struct TextQuery<'a> {
+    index: &'a TextIndex,
+    // per query word: matching indexed word id -> score
+    words: Vec<HashMap<usize, f64>>,
+}
+
+impl TextQuery<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Relevance of a row given its values and the weight of their fields, or None if some
+    /// query word matches none of them.
+    pub fn score(&self, fields: &[(Option<&str>, f64)]) -> Option<f64> {
+        let mut total = 0.0;
+        for matches in &self.words {
+            let best = fields
+                .iter()
+                .filter_map(|(value, weight)| {
+                    let ids = self.index.values.get((*value)?)?;
+                    ids.iter()
+                        .filter_map(|id| matches.get(id))
+                        .cloned()
+                        .reduce(f64::max)
+                        .map(|score| score * weight)
+                })
+                .reduce(f64::max)?;
+            total += best;
+        }
+        Some(total)
+    }
+}
+
+/// Lowercase words of a value, `payments.txn-events` gives payments, txn and events.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+// Exact, prefix, typo and typo-in-prefix matches, in that order of preference.
+fn word_score(query_word: &str, word: &str) -> Option<f64> {
+    if word == query_word {
+        return Some(EXACT_SCORE);
+    }
+    let query_len = query_word.chars().count();
+    let word_len = word.chars().count();
+    if query_len >= 2 && word.starts_with(query_word) {
+        // the more of the word is typed, the better
+        return Some(PREFIX_SCORE + 0.2 * query_len as f64 / word_len as f64);
+    }
+    let max_typos = match query_len {
+        0..=3 => return None,
+        4..=7 => 1,
+        _ => 2,
+    };
+    if word_len + max_typos < query_len {
+        return None;
+    }
+    let (typos, prefix_typos) = edit_distances(query_word, word);
+    if typos <= max_typos {
+        Some(TYPO_SCORE - TYPO_PENALTY * (typos - 1) as f64)
+    } else if prefix_typos <= max_typos {
+        Some(TYPO_PREFIX_SCORE - TYPO_PENALTY * (prefix_typos - 1) as f64)
+    } else {
+        None
+    }
+}
+
+// Levenshtein distance of `a` to `b`, and the smallest distance of `a` to a prefix of `b`.
+fn edit_distances(a: &str, b: &str) -> (usize, usize) {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // previous[j] is the distance of the first i chars of a to the first j chars of b
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    let prefix = previous.iter().cloned().min().unwrap_or(a.len());
+    (previous[b.len()], prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_service::COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE;
+
+    fn index(topics: &[&str]) -> TextIndex {
+        let ds_inventory = df!(
+            COL_APP_OWNER_INVENTORY_FILE => vec!["payments"; topics.len()],
+            COL_TOPIC_NAME_INVENTORY_FILE => topics,
+        )
+        .unwrap();
+        let ds_consumer = df!(
+            COL_CONSUMER_APP_NAME_CONSUMER_FILE => ["ledger"],
+            COL_CONSUMER_TOPIC_NAME_CONSUMER_FILE => [topics[0]],
+            COL_CONSUMER_GROUP_NAME_CONSUMER_FILE => ["ledger-group"],
+        )
+        .unwrap();
+        TextIndex::build(&ds_inventory, &ds_consumer).unwrap()
+    }
+
+    fn score(index: &TextIndex, text: &str, topic: &str) -> Option<f64> {
+        index.query(text).score(&[(Some(topic), 1.0)])
+    }
+
+    #[test]
+    fn tokenize_splits_on_punctuation_and_lowercases() {
+        assert_eq!(
+            tokenize("Payments.TXN-events_v1  Café"),
+            ["payments", "txn", "events", "v1", "café"]
+        );
+        assert!(tokenize(" .-_ ").is_empty());
+    }
+
+    #[test]
+    fn exact_beats_prefix_beats_typo() {
+        assert_eq!(word_score("payments", "payments"), Some(EXACT_SCORE));
+        // more of the word typed scores higher
+        let short_prefix = word_score("pay", "payments").unwrap();
+        let long_prefix = word_score("paymen", "payments").unwrap();
+        assert!((short_prefix - 0.775).abs() < 1e-9, "{}", short_prefix);
+        assert!(long_prefix > short_prefix);
+        assert!(long_prefix < EXACT_SCORE);
+        assert_eq!(word_score("paymnts", "payments"), Some(TYPO_SCORE));
+        assert_eq!(word_score("paymnt", "payments"), Some(TYPO_PREFIX_SCORE));
+        assert!(TYPO_SCORE < short_prefix);
+        // a single letter is not a prefix
+        assert_eq!(word_score("p", "payments"), None);
+    }
+
+    #[test]
+    fn longer_words_allow_more_typos() {
+        // up to three letters must match exactly
+        assert_eq!(word_score("txm", "txn"), None);
+        // four to seven letters allow one typo
+        assert_eq!(word_score("ledgr", "ledger"), Some(TYPO_SCORE));
+        assert_eq!(word_score("ledgxx", "ledger"), None);
+        // eight and more allow two, the second one costs a little
+        assert_eq!(
+            word_score("paymnets", "payments"),
+            Some(TYPO_SCORE - TYPO_PENALTY)
+        );
+        assert_eq!(word_score("pxymxntx", "payments"), None);
+    }
+
+    #[test]
+    fn edit_distances_of_whole_word_and_best_prefix() {
+        assert_eq!(edit_distances("kitten", "sitting"), (3, 2));
+        assert_eq!(edit_distances("paymnt", "payments"), (2, 1));
+        assert_eq!(edit_distances("txn", "txn"), (0, 0));
+        assert_eq!(edit_distances("", "txn"), (3, 0));
+    }
+
+    #[test]
+    fn typos_still_find_the_topic() {
+        let index = index(&["payments.txn-events", "orders.created"]);
+        assert!(score(&index, "paymnt txn", "payments.txn-events").is_some());
+        // txn matches nothing in the orders topic
+        assert_eq!(score(&index, "paymnt txn", "orders.created"), None);
+        // a word matching nothing anywhere gives no score at all
+        assert_eq!(score(&index, "payments zebra", "payments.txn-events"), None);
+    }
+
+    #[test]
+    fn rows_rank_by_the_best_match_of_each_word() {
+        let index = index(&["payments.txn", "paymentservice.txn", "pymnts.txn"]);
+        let exact = score(&index, "payments txn", "payments.txn").unwrap();
+        let prefix = score(&index, "payments txn", "paymentservice.txn").unwrap();
+        let typo = score(&index, "payments txn", "pymnts.txn").unwrap();
+        assert!(
+            exact > prefix && prefix > typo,
+            "{} {} {}",
+            exact,
+            prefix,
+            typo
+        );
+        assert_eq!(exact, 2.0 * EXACT_SCORE);
+
+        // the weight of the field scales its score
+        let query = index.query("ledger");
+        let weighted = query
+            .score(&[(Some("payments.txn"), 1.0), (Some("ledger"), 0.5)])
+            .unwrap();
+        assert_eq!(weighted, 0.5);
+        assert!(query.score(&[(None, 1.0)]).is_none());
+        assert!(index.query(" - ").is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{Duration, Utc};
+use log::{debug, error, info};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
+
+use crate::authorization::Role;
+use crate::entities::{APIError, Claims, ErrorCode};
+
+// Leeway jsonwebtoken gives `exp` when validating, tokens live this much past their TTL
+const CLOCK_SKEW_SECS: i64 = 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RefreshTokenRecord {
+    #[serde(rename = "user_id")]
+    user_id: String,
+    #[serde(rename = "roles")]
+    roles: Vec<Role>,
+    // every rotation of one login shares the family, reuse of a rotated token revokes all of it
+    #[serde(rename = "family")]
+    family: String,
+    #[serde(rename = "expires_at")]
+    expires_at: i64,
+    #[serde(rename = "rotated")]
+    rotated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct StoreData {
+    // jti -> expiry of the revoked token, dropped once it has expired anyway
+    #[serde(rename = "revoked_jtis", default)]
+    revoked_jtis: HashMap<String, i64>,
+    // sub -> time of revocation, tokens issued until then are revoked
+    #[serde(rename = "revoked_subjects", default)]
+    revoked_subjects: HashMap<String, i64>,
+    // SHA-256 of the refresh token -> record
+    #[serde(rename = "refresh_tokens", default)]
+    refresh_tokens: HashMap<String, RefreshTokenRecord>,
+}
+
+/// A refresh token exchanged for a new one.
+pub struct RotatedRefreshToken {
+    pub refresh_token: String,
+    pub user_id: String,
+    pub roles: Vec<Role>,
+}
+
+/// Refresh tokens and revoked access tokens, written to a local JSON file on every change
+/// so that revocations survive a restart.
+pub struct TokenStore {
+    path: PathBuf,
+    refresh_ttl: Duration,
+    // subject revocations only need to outlive the access tokens issued before them
+    subject_revocation_retention: Duration,
+    data: Mutex<StoreData>,
+}
+
+impl TokenStore {
+    pub fn open(
+        path: impl Into<PathBuf>,
+        refresh_ttl: Duration,
+        access_token_ttl: Duration,
+    ) -> Result<TokenStore, APIError> {
+        let path = path.into();
+        let data = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| {
+                APIError::new(&format!("Invalid token store {}: {}", path.display(), e))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => StoreData::default(),
+            Err(e) => {
+                return Err(APIError::new(&format!(
+                    "Failed to read token store {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        info!(
+            "Token store {}: {} refresh tokens, {} revoked tokens",
+            path.display(),
+            data.refresh_tokens.len(),
+            data.revoked_jtis.len()
+        );
+        Ok(TokenStore {
+            path,
+            refresh_ttl,
+            subject_revocation_retention: access_token_ttl + Duration::seconds(CLOCK_SKEW_SECS),
+            data: Mutex::new(data),
+        })
+    }
+
+    /// Open the store at `TOKEN_STORE_FILE` (default `token_store.json`), with refresh tokens
+    /// valid for `REFRESH_TOKEN_TTL_SECS` (default 7 days) and revocations kept as long as
+    /// access tokens issued before them are valid.
+    pub fn from_env(access_token_ttl: Duration) -> Result<TokenStore, APIError> {
+        let path = std::env::var("TOKEN_STORE_FILE").unwrap_or("token_store.json".to_string());
+        let ttl_secs = std::env::var("REFRESH_TOKEN_TTL_SECS")
+            .unwrap_or("604800".to_string())
+            .parse::<i64>()
+            .map_err(|_| APIError::new("REFRESH_TOKEN_TTL_SECS must be a number of seconds"))?;
+        TokenStore::open(path, Duration::seconds(ttl_secs), access_token_ttl)
+    }
+
+    pub fn refresh_ttl(&self) -> Duration {
+        self.refresh_ttl
+    }
+
+    /// Start a new refresh token family for a login.
+    pub fn issue_refresh_token(&self, user_id: &str, roles: &[Role]) -> Result<String, APIError> {
+        let mut data = self.data.lock().unwrap();
+        let token = self.insert_refresh_token(&mut data, user_id, roles, random_token(32));
+        self.save(&mut data)?;
+        Ok(token)
+    }
+
+    /// Exchange a refresh token for a new one of the same family. Presenting an already
+    /// rotated token means it leaked, so the whole family is revoked.
+    pub fn rotate_refresh_token(&self, token: &str) -> Result<RotatedRefreshToken, APIError> {
+        let hash = token_hash(token);
+        let now = Utc::now().timestamp();
+        let mut data = self.data.lock().unwrap();
+        let record = match data.refresh_tokens.get(&hash) {
+            Some(record) if record.expires_at > now => record.clone(),
+            _ => {
+                return Err(APIError::with_code(
+                    ErrorCode::Unauthorized,
+                    "Invalid or expired refresh token",
+                ))
+            }
+        };
+        if record.rotated {
+            error!(
+                "Reuse of a rotated refresh token of {}, revoking its family",
+                record.user_id
+            );
+            data.refresh_tokens.retain(|_, r| r.family != record.family);
+            self.save(&mut data)?;
+            return Err(APIError::with_code(
+                ErrorCode::Unauthorized,
+                "Invalid or expired refresh token",
+            ));
+        }
+
+        data.refresh_tokens.get_mut(&hash).unwrap().rotated = true;
+        let refresh_token =
+            self.insert_refresh_token(&mut data, &record.user_id, &record.roles, record.family);
+        self.save(&mut data)?;
+        Ok(RotatedRefreshToken {
+            refresh_token,
+            user_id: record.user_id,
+            roles: record.roles,
+        })
+    }
+
+    /// Revoke the family of a refresh token, e.g. on logout. Unknown tokens are ignored.
+    pub fn revoke_refresh_token(&self, token: &str) -> Result<(), APIError> {
+        let mut data = self.data.lock().unwrap();
+        let family = match data.refresh_tokens.get(&token_hash(token)) {
+            Some(record) => record.family.clone(),
+            None => return Ok(()),
+        };
+        data.refresh_tokens.retain(|_, r| r.family != family);
+        self.save(&mut data)
+    }
+
+    /// Revoke one access token until it expires.
+    pub fn revoke_jti(&self, jti: &str, exp: usize) -> Result<(), APIError> {
+        if jti.is_empty() {
+            return Ok(());
+        }
+        let mut data = self.data.lock().unwrap();
+        data.revoked_jtis.insert(jti.to_string(), exp as i64);
+        self.save(&mut data)
+    }
+
+    /// Revoke every token issued to `sub` so far and drop its refresh tokens.
+    pub fn revoke_subject(&self, sub: &str) -> Result<(), APIError> {
+        let mut data = self.data.lock().unwrap();
+        data.revoked_subjects
+            .insert(sub.to_string(), Utc::now().timestamp());
+        data.refresh_tokens.retain(|_, r| r.user_id != sub);
+        self.save(&mut data)
+    }
+
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        let data = self.data.lock().unwrap();
+        if !claims.jti().is_empty() && data.revoked_jtis.contains_key(claims.jti()) {
+            return true;
+        }
+        data.revoked_subjects
+            .get(claims.sub())
+            .is_some_and(|revoked_at| claims.iat() as i64 <= *revoked_at)
+    }
+
+    fn insert_refresh_token(
+        &self,
+        data: &mut StoreData,
+        user_id: &str,
+        roles: &[Role],
+        family: String,
+    ) -> String {
+        let token = random_token(48);
+        data.refresh_tokens.insert(
+            token_hash(&token),
+            RefreshTokenRecord {
+                user_id: user_id.to_string(),
+                roles: roles.to_vec(),
+                family,
+                expires_at: (Utc::now() + self.refresh_ttl).timestamp(),
+                rotated: false,
+            },
+        );
+        token
+    }
+
+    // Drop what has expired and write the store.
+    fn save(&self, data: &mut StoreData) -> Result<(), APIError> {
+        let now = Utc::now().timestamp();
+        let retention = self.subject_revocation_retention.num_seconds();
+        data.revoked_jtis
+            .retain(|_, exp| *exp + CLOCK_SKEW_SECS > now);
+        data.revoked_subjects
+            .retain(|_, revoked_at| *revoked_at + retention > now);
+        data.refresh_tokens.retain(|_, r| r.expires_at > now);
+
+        write_json_file(&self.path, data).map_err(|e| {
+            error!("Failed to write token store {}: {}", self.path.display(), e);
+            APIError::new("Failed to save the token store")
+        })?;
+        debug!("Saved token store {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// Random string for token ids and refresh tokens.
+pub fn random_token(len: usize) -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), len)
+}
+
+/// Write `value` as JSON to a temporary file renamed over `path`, so a crash never leaves half a file.
+pub fn write_json_file<T: Serialize>(path: &Path, value: &T) -> std::io::Result<()> {
+    let json = serde_json::to_string(value)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Hex SHA-256 of a secret token. Tokens are only stored hashed, a copy of a store can't be
+/// used to log in.
+pub fn token_hash(token: &str) -> String {
+    ring::digest::digest(&ring::digest::SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -1,7 +1,7 @@
 import { initializeMermaid, renderMermaid } from './mermaid-config.js';
 import {  renderTable} from './table.js';
 import { filterFunction, handleKeyDown , selectItem} from './searchable-dropdown.js';
-import { checkTokenValidity } from './token-handler.js';
+import { checkTokenValidity, refreshAccessToken } from './token-handler.js';
 import { renderMarked } from './marked-config.js';
 
 function load_filter_table() {
@@ -264,8 +264,8 @@ function get_search_data_req() {
     return json_data_req;
 }
 
-// Fetch data from the API with an access token
-function fetchDataWithAccessToken(apiEndpoint, method, body,isJson = true) {
+// Fetch data from the API with an access token, refreshing it once if it was rejected
+function fetchDataWithAccessToken(apiEndpoint, method, body, isJson = true, retry = true) {
     // Fetch data from the API
     let accessToken = localStorage.getItem('token');
     let params = {
@@ -283,14 +283,23 @@ function fetchDataWithAccessToken(apiEndpoint, method, body,isJson = true) {
 
     return fetch(apiEndpoint, params)
         .then(response => {
+            if (response.status === 401 && retry) {
+                return refreshAccessToken().then(refreshed => {
+                    if (!refreshed) {
+                        window.location.href = 'login.html';
+                        throw new Error('Session expired');
+                    }
+                    return fetchDataWithAccessToken(apiEndpoint, method, body, isJson, false);
+                });
+            }
             if (!response.ok) {
                 if (isJson) {
                     return response.json().then(errData => {
-                        throw new Error(errData.error || `HTTP error! status: ${response.status}`);
+                        throw new Error(errData.message || `HTTP error! status: ${response.status}`);
                     });
                 }else{
                     return response.text().then(errData => {
-                        throw new Error(errData.error || `HTTP error! status: ${response.status}`);
+                        throw new Error(errData.message || `HTTP error! status: ${response.status}`);
                     });
                 }
             }
@@ -480,8 +489,8 @@ function search_able_dropdown_topic_name_handler() {
 
 
 
-document.addEventListener('DOMContentLoaded', function() {
-    if (!checkTokenValidity()) {
+document.addEventListener('DOMContentLoaded', async function() {
+    if (!checkTokenValidity() && !(await refreshAccessToken())) {
         window.location.href = 'login.html'; // Redirect to login if token is invalid or expired
     }
     console.log("script.js loaded");
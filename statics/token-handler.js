@@ -13,4 +13,26 @@ export function checkTokenValidity() {
     }
 
     return true;
-}
\ No newline at end of file
+}
+
+// Exchange the stored refresh token for a new access token, false if there is none or it was rejected
+export async function refreshAccessToken() {
+    const refreshToken = localStorage.getItem('refreshToken');
+    if (!refreshToken) {
+        return false;
+    }
+    const response = await fetch('/api/authenticate/v1/refresh', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ refresh_token: refreshToken })
+    });
+    if (!response.ok) {
+        localStorage.removeItem('refreshToken');
+        return false;
+    }
+    const data = await response.json();
+    localStorage.setItem('expirationTime', new Date().getTime() + data.data.expires_in * 1000);
+    localStorage.setItem('token', data.data.token);
+    localStorage.setItem('refreshToken', data.data.refresh_token);
+    return true;
+}